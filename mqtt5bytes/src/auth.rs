@@ -0,0 +1,251 @@
+//! MQTT 5 enhanced authentication (spec section 4.12): a pluggable [`AuthMethod`] drives a
+//! sequence of `AUTH` packets. `Connect`'s `authentication_method`/`authentication_data`
+//! properties carry the first round (`AuthMethod::initial`); every `AUTH` packet after that
+//! feeds its `authentication_data` to `AuthMethod::step` until it reports [`AuthStep::Done`],
+//! at which point the caller waits for `ConnAck` instead of sending another `AUTH`.
+//!
+//! The hashing/HMAC behind the bundled [`ScramSha256`] method is provided by one of two
+//! mutually exclusive backends, selected at compile time: `auth-rustcrypto` (pure-Rust
+//! `sha2`/`hmac`/`pbkdf2`) or `auth-ring` (`ring`).
+
+#[cfg(all(feature = "auth-rustcrypto", feature = "auth-ring"))]
+compile_error!("features `auth-rustcrypto` and `auth-ring` are mutually exclusive - pick one SCRAM backend");
+
+#[cfg(not(any(feature = "auth-rustcrypto", feature = "auth-ring")))]
+compile_error!("the `auth` feature needs exactly one backend feature enabled: `auth-rustcrypto` or `auth-ring`");
+
+use alloc::format;
+use alloc::string::String;
+use bytes::Bytes;
+
+/// What an [`AuthMethod`] wants to happen next after seeing the server's last `AUTH` packet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthStep {
+    /// Send another `AUTH` packet, reason code
+    /// [`ReasonCode::CONTINUE_AUTHENTICATION`](crate::reasoncodes::ReasonCode::CONTINUE_AUTHENTICATION),
+    /// carrying this as `authentication_data`.
+    Continue(Bytes),
+    /// The method is satisfied; wait for the server's `ConnAck`.
+    Done,
+}
+
+/// An MQTT 5 enhanced authentication method - the thing named by `Connect`'s
+/// `authentication_method` property and driven by repeated `AUTH` exchanges instead of only
+/// plain username/password.
+pub trait AuthMethod {
+    /// Sent as `Connect`'s `authentication_method` property, e.g. `"SCRAM-SHA-256"`.
+    fn name(&self) -> &str;
+
+    /// `authentication_data` to put on the initial `Connect`.
+    fn initial(&self) -> Bytes;
+
+    /// Feed in the server's `authentication_data` from the last `AUTH` packet.
+    fn step(&mut self, server_data: &[u8]) -> AuthStep;
+}
+
+/// The actual crypto, isolated behind a tiny internal interface so swapping `auth-rustcrypto`
+/// for `auth-ring` (or adding a third backend later) never touches [`ScramSha256`] itself.
+mod backend {
+    #[cfg(feature = "auth-rustcrypto")]
+    pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+        use hmac::{Hmac, Mac, NewMac};
+        use sha2::Sha256;
+
+        let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC-SHA256 accepts keys of any length");
+        mac.update(data);
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&mac.finalize().into_bytes());
+        out
+    }
+
+    #[cfg(feature = "auth-rustcrypto")]
+    pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&Sha256::digest(data));
+        out
+    }
+
+    #[cfg(feature = "auth-rustcrypto")]
+    pub(crate) fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+        use sha2::Sha256;
+
+        let mut out = [0u8; 32];
+        pbkdf2::pbkdf2::<hmac::Hmac<Sha256>>(password, salt, iterations, &mut out);
+        out
+    }
+
+    #[cfg(feature = "auth-ring")]
+    pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+        use ring::hmac;
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        let mut out = [0u8; 32];
+        out.copy_from_slice(hmac::sign(&key, data).as_ref());
+        out
+    }
+
+    #[cfg(feature = "auth-ring")]
+    pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+        use ring::digest;
+
+        let mut out = [0u8; 32];
+        out.copy_from_slice(digest::digest(&digest::SHA256, data).as_ref());
+        out
+    }
+
+    #[cfg(feature = "auth-ring")]
+    pub(crate) fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+        use core::num::NonZeroU32;
+        use ring::pbkdf2;
+
+        let mut out = [0u8; 32];
+        let iterations = NonZeroU32::new(iterations).expect("SCRAM iteration count must be non-zero");
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password, &mut out);
+        out
+    }
+}
+
+fn xor(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    for i in 0..32 {
+        out[i] = a[i] ^ b[i];
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ScramState {
+    ClientFirstSent,
+    ClientFinalSent,
+    Done,
+}
+
+/// SCRAM-SHA-256 (RFC 5802 / RFC 7677), the first [`AuthMethod`] this crate ships.
+///
+/// `client_nonce` is caller-supplied randomness - this crate has no RNG of its own, so callers
+/// must pass a fresh, unpredictable nonce for every authentication attempt.
+pub struct ScramSha256 {
+    password: String,
+    client_nonce: String,
+    client_first_bare: String,
+    auth_message: String,
+    salted_password: [u8; 32],
+    state: ScramState,
+    // `None` until the server-final-message has been checked; `Some(false)` means the server
+    // couldn't prove it knows the stored secret and the caller should abort the connection.
+    server_verified: Option<bool>,
+}
+
+impl ScramSha256 {
+    pub fn new(username: String, password: String, client_nonce: String) -> ScramSha256 {
+        let client_first_bare = format!("n={},r={}", username, client_nonce);
+
+        ScramSha256 {
+            password,
+            client_nonce,
+            client_first_bare,
+            auth_message: String::new(),
+            salted_password: [0u8; 32],
+            state: ScramState::ClientFirstSent,
+            server_verified: None,
+        }
+    }
+
+    /// `None` until the server-final-message has arrived; `Some(false)` means the handshake
+    /// completed but the server never proved it knew the stored secret.
+    pub fn server_verified(&self) -> Option<bool> {
+        self.server_verified
+    }
+}
+
+impl AuthMethod for ScramSha256 {
+    fn name(&self) -> &str {
+        "SCRAM-SHA-256"
+    }
+
+    fn initial(&self) -> Bytes {
+        // gs2 header "n,," = no channel binding, no authzid.
+        Bytes::from(format!("n,,{}", self.client_first_bare))
+    }
+
+    fn step(&mut self, server_data: &[u8]) -> AuthStep {
+        match self.state {
+            ScramState::ClientFirstSent => self.handle_server_first(server_data),
+            ScramState::ClientFinalSent => self.handle_server_final(server_data),
+            ScramState::Done => AuthStep::Done,
+        }
+    }
+}
+
+impl ScramSha256 {
+    fn handle_server_first(&mut self, server_data: &[u8]) -> AuthStep {
+        let server_first = match core::str::from_utf8(server_data) {
+            Ok(s) => s,
+            // not valid SCRAM - nothing sane left to do but give up on this method.
+            Err(_) => {
+                self.state = ScramState::Done;
+                return AuthStep::Done;
+            }
+        };
+
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for field in server_first.split(',') {
+            if let Some(value) = field.strip_prefix("r=") {
+                nonce = Some(value);
+            } else if let Some(value) = field.strip_prefix("s=") {
+                salt = base64::decode(value).ok();
+            } else if let Some(value) = field.strip_prefix("i=") {
+                iterations = value.parse::<u32>().ok();
+            }
+        }
+
+        let (nonce, salt, iterations) = match (nonce, salt, iterations) {
+            (Some(nonce), Some(salt), Some(iterations)) if nonce.starts_with(self.client_nonce.as_str()) => {
+                (nonce, salt, iterations)
+            }
+            // malformed server-first-message, or the server dropped our nonce - give up.
+            _ => {
+                self.state = ScramState::Done;
+                return AuthStep::Done;
+            }
+        };
+
+        self.salted_password = backend::pbkdf2_hmac_sha256(self.password.as_bytes(), &salt, iterations);
+        let client_final_without_proof = format!("c=biws,r={}", nonce);
+        self.auth_message = format!("{},{},{}", self.client_first_bare, server_first, client_final_without_proof);
+
+        let client_key = backend::hmac_sha256(&self.salted_password, b"Client Key");
+        let stored_key = backend::sha256(&client_key);
+        let client_signature = backend::hmac_sha256(&stored_key, self.auth_message.as_bytes());
+        let client_proof = xor(&client_key, &client_signature);
+
+        self.state = ScramState::ClientFinalSent;
+        let client_final = format!("{},p={}", client_final_without_proof, base64::encode(&client_proof));
+        AuthStep::Continue(Bytes::from(client_final))
+    }
+
+    /// Verifies the server's `v=<ServerSignature>` against what we derived ourselves. SCRAM
+    /// has nothing left to send either way, so this always reports `Done` - the result lands
+    /// in [`ScramSha256::server_verified`] for the caller to check before trusting `ConnAck`.
+    fn handle_server_final(&mut self, server_data: &[u8]) -> AuthStep {
+        self.server_verified = Some(
+            core::str::from_utf8(server_data)
+                .ok()
+                .and_then(|server_final| server_final.strip_prefix("v="))
+                .and_then(|v| base64::decode(v).ok())
+                .map(|server_signature| {
+                    let server_key = backend::hmac_sha256(&self.salted_password, b"Server Key");
+                    let expected = backend::hmac_sha256(&server_key, self.auth_message.as_bytes());
+                    server_signature.as_slice() == expected
+                })
+                .unwrap_or(false),
+        );
+
+        self.state = ScramState::Done;
+        AuthStep::Done
+    }
+}
@@ -0,0 +1,37 @@
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{mqtt_read_incremental, mqtt_write, Error, Packet};
+
+/// An async codec that frames `Packet`s off a byte stream. Wrap a `TcpStream`/`TlsStream`
+/// in `tokio_util::codec::Framed` using this to get a `Stream`/`Sink` of MQTT packets.
+#[derive(Debug, Clone)]
+pub struct Codec {
+    /// Maximum allowed size of a single packet's remaining length
+    pub max_payload_size: usize,
+}
+
+impl Codec {
+    pub fn new(max_payload_size: usize) -> Codec {
+        Codec { max_payload_size }
+    }
+}
+
+impl Decoder for Codec {
+    type Item = Packet;
+    type Error = Error;
+
+    fn decode(&mut self, stream: &mut BytesMut) -> Result<Option<Packet>, Error> {
+        mqtt_read_incremental(stream, self.max_payload_size)
+    }
+}
+
+impl Encoder<Packet> for Codec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: Packet, buf: &mut BytesMut) -> Result<(), Error> {
+        let bytes: Bytes = mqtt_write(packet)?;
+        buf.extend_from_slice(&bytes);
+        Ok(())
+    }
+}
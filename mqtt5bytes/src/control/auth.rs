@@ -1,26 +1,59 @@
-use crate::Error;
+use crate::{encode_binary_data, encode_utf_string, encode_utf_string_pair, encode_variable_byte, Error, Utf8Pair};
 use crate::FixedHeader;
 
-use bytes::Bytes;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use alloc::string::String;
-use crate::control::properties::extract_properties;
+use alloc::vec::Vec;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct AuthProperties {
     pub authentication_method: Option<String>,
-    pub authentication_data: Option<String>,
+    pub authentication_data: Option<Bytes>,
     pub reason_string: Option<String>,
-    pub user_property: Option<String>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
+impl AuthProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(authentication_method) = &self.authentication_method {
+            props.put_u8(PropertyIdentifiers::AUTHENTICATION_METHOD);
+            props.extend_from_slice(&encode_utf_string(authentication_method.clone())?);
+        }
+        if let Some(authentication_data) = &self.authentication_data {
+            props.put_u8(PropertyIdentifiers::AUTHENTICATION_DATA);
+            props.extend_from_slice(&encode_binary_data(authentication_data.clone())?);
+        }
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Auth {
+    pub reason_code: u8,
     pub properties: Option<AuthProperties>
 }
 
 impl Auth {
     pub(crate) fn assemble(fixed_header: FixedHeader, mut bytes: Bytes) -> Result<Self, Error> {
-        let _props = extract_properties(&mut bytes)?;
+        let variable_header_index = fixed_header.header_len;
+        bytes.advance(variable_header_index);
+        let reason_code = bytes.get_u8();
+
+        let _props = extract_properties(&mut bytes, PropertyOwner::Auth)?;
         let auth = match _props {
             Some(props) => {
                 let properties = Some(
@@ -28,13 +61,38 @@ impl Auth {
                         authentication_method: props.authentication_method,
                         authentication_data: props.authentication_data,
                         reason_string: props.reason_string,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                     }
                 );
-                Auth { properties }
+                Auth { reason_code, properties }
             }
-            None => Auth { properties: None }
+            None => Auth { reason_code, properties: None }
         };
         Ok(auth)
     }
 }
+
+impl Auth {
+    pub fn new(reason_code: u8, properties: Option<AuthProperties>) -> Auth {
+        Auth { reason_code, properties }
+    }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u8(self.reason_code);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b1111_0000); // AUTH, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte(var_header.len() as i32)?);
+        packet.extend_from_slice(&var_header);
+
+        Ok(packet.to_bytes())
+    }
+}
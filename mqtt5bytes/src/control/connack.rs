@@ -1,8 +1,9 @@
-use crate::{Error, FixedHeader, ByteLengths, Utf8Pair};
-use bytes::{Buf, Bytes};
+use crate::{encode_binary_data, encode_utf_string, encode_utf_string_pair, encode_variable_byte, connack_reason_code, ConnAckReason, Error, FixedHeader, ByteLengths, Utf8Pair};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use crate::reasoncodes::ReasonCode;
 use alloc::string::String;
-use crate::control::properties::{PropertyIdentifiers, Properties, extract_properties};
+use alloc::vec::Vec;
+use crate::control::properties::{PropertyIdentifiers, Properties, extract_properties, PropertyOwner};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ConnackProperties {
@@ -10,11 +11,11 @@ pub struct ConnackProperties {
     pub assigned_client_identifier: Option<String>,
     pub server_keep_alive: Option<u16>,
     pub authentication_method: Option<String>,
-    pub authentication_data: Option<String>,
+    pub authentication_data: Option<Bytes>,
     pub response_info: Option<String>,
     pub server_info: Option<String>,
     pub reason_string: Option<String>,
-    pub user_property: Option<Utf8Pair>,
+    pub user_properties: Vec<Utf8Pair>,
     pub receive_maximum: Option<u16>,
     pub topic_alias_maximum: Option<u16>,
     pub maximum_qos: Option<u8>,
@@ -25,10 +26,85 @@ pub struct ConnackProperties {
     pub shared_subscription_available: Option<u8>,
 }
 
+impl ConnackProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(session_expiry_interval) = self.session_expiry_interval {
+            props.put_u8(PropertyIdentifiers::SESSION_EXPIRY_INTERVAL);
+            props.put_u32(session_expiry_interval);
+        }
+        if let Some(assigned_client_identifier) = &self.assigned_client_identifier {
+            props.put_u8(PropertyIdentifiers::ASSIGNED_CLIENT_IDENTIFIER);
+            props.extend_from_slice(&encode_utf_string(assigned_client_identifier.clone())?);
+        }
+        if let Some(server_keep_alive) = self.server_keep_alive {
+            props.put_u8(PropertyIdentifiers::SERVER_KEEP_ALIVE);
+            props.put_u16(server_keep_alive);
+        }
+        if let Some(authentication_method) = &self.authentication_method {
+            props.put_u8(PropertyIdentifiers::AUTHENTICATION_METHOD);
+            props.extend_from_slice(&encode_utf_string(authentication_method.clone())?);
+        }
+        if let Some(authentication_data) = &self.authentication_data {
+            props.put_u8(PropertyIdentifiers::AUTHENTICATION_DATA);
+            props.extend_from_slice(&encode_binary_data(authentication_data.clone())?);
+        }
+        if let Some(response_info) = &self.response_info {
+            props.put_u8(PropertyIdentifiers::RESPONSE_INFO);
+            props.extend_from_slice(&encode_utf_string(response_info.clone())?);
+        }
+        if let Some(server_info) = &self.server_info {
+            props.put_u8(PropertyIdentifiers::SERVER_INFO);
+            props.extend_from_slice(&encode_utf_string(server_info.clone())?);
+        }
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        if let Some(receive_maximum) = self.receive_maximum {
+            props.put_u8(PropertyIdentifiers::RECEIVE_MAXIMUM);
+            props.put_u16(receive_maximum);
+        }
+        if let Some(topic_alias_maximum) = self.topic_alias_maximum {
+            props.put_u8(PropertyIdentifiers::TOPIC_ALIAS_MAXIMUM);
+            props.put_u16(topic_alias_maximum);
+        }
+        if let Some(maximum_qos) = self.maximum_qos {
+            props.put_u8(PropertyIdentifiers::MAXIMUM_QOS);
+            props.put_u8(maximum_qos);
+        }
+        if let Some(retain_available) = self.retain_available {
+            props.put_u8(PropertyIdentifiers::RETAIN_AVAILABLE);
+            props.put_u8(retain_available);
+        }
+        if let Some(maximum_packet_size) = self.maximum_packet_size {
+            props.put_u8(PropertyIdentifiers::MAXIMUM_PACKET_SIZE);
+            props.put_u32(maximum_packet_size);
+        }
+        if let Some(wildcard_subscription_available) = self.wildcard_subscription_available {
+            props.put_u8(PropertyIdentifiers::WILDCARD_SUBSCRIPTION_AVAILABLE);
+            props.put_u8(wildcard_subscription_available);
+        }
+        if let Some(subscription_identifier_available) = self.subscription_identifier_available {
+            props.put_u8(PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER_AVAILABLE);
+            props.put_u8(subscription_identifier_available);
+        }
+        if let Some(shared_subscription_available) = self.shared_subscription_available {
+            props.put_u8(PropertyIdentifiers::SHARED_SUBSCRIPTION_AVAILABLE);
+            props.put_u8(shared_subscription_available);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ConnAck {
     pub session_present: bool,
-    pub reason_code: u8,
+    pub reason_code: ConnAckReason,
     pub properties: Option<ConnackProperties>,
 }
 
@@ -38,16 +114,16 @@ impl ConnAck {
         let variable_header_index = fixed_header.header_len;
         bytes.advance(variable_header_index);
 
-        if fixed_header.remaining_len != 2 {
+        if fixed_header.remaining_len < 2 {
             return Err(Error::PayloadSizeIncorrect);
         }
 
         let flags = bytes.get_u8();
         let session_present = (flags & 0x01) == 1;
 
-        let reason_code = bytes.get_u8();
+        let reason_code = connack_reason_code(bytes.get_u8())?;
 
-        let _props = extract_properties(&mut bytes)?;
+        let _props = extract_properties(&mut bytes, PropertyOwner::ConnAck)?;
 
         let connack = match _props {
             Some(props) => {
@@ -61,7 +137,7 @@ impl ConnAck {
                         response_info: props.response_info,
                         server_info: props.server_info,
                         reason_string: props.reason_string,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                         receive_maximum: props.receive_maximum,
                         topic_alias_maximum: props.topic_alias_maximum,
                         maximum_qos: props.maximum_qos,
@@ -84,9 +160,29 @@ impl ConnAck {
 }
 
 impl ConnAck {
-    pub fn new(reason_code: u8, session_present: bool, properties: Option<ConnackProperties>) -> ConnAck {
+    pub fn new(reason_code: ConnAckReason, session_present: bool, properties: Option<ConnackProperties>) -> ConnAck {
         ConnAck { session_present, reason_code, properties }
     }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u8(self.session_present as u8);
+        var_header.put_u8(self.reason_code as u8);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b0010_0000); // CONNACK, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte(var_header.len() as i32)?);
+        packet.extend_from_slice(&var_header);
+
+        Ok(packet.to_bytes())
+    }
 }
 
 // #[cfg(test)]
@@ -130,3 +226,26 @@ impl ConnAck {
 //         );
 //     }
 // }
+
+#[cfg(test)]
+mod test_connack {
+    use crate::*;
+    use bytes::BytesMut;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn connack_write_and_read_round_trips() {
+        let packet = ConnAck::new(ConnAckReason::Success, true, None);
+
+        let bytes = mqtt_write(Packet::ConnAck(packet.clone())).unwrap();
+        let mut stream = BytesMut::from(&bytes[..]);
+
+        let read_back = mqtt_read(&mut stream, 100).unwrap();
+        let read_back = match read_back {
+            Packet::ConnAck(packet) => packet,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(read_back, packet);
+    }
+}
@@ -1,10 +1,11 @@
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt;
 
 use bytes::{Buf, Bytes, BytesMut, BufMut};
 
-use crate::{decode_variable_byte, Error, extract_mqtt_string, FixedHeader, LastWill, Protocol, qos, Utf8Pair, PacketType, encode_utf_string};
-use crate::control::properties::extract_properties;
+use crate::{decode_binary_data, decode_variable_byte, encode_binary_data, encode_variable_byte, Error, extract_mqtt_string, FixedHeader, LastWill, Protocol, qos, Utf8Pair, PacketType, encode_utf_string, encode_utf_string_pair};
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
 use crate::Protocol::MQTT;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -15,14 +16,51 @@ pub struct ConnectProperties {
     pub topic_alias_maximum: Option<u16>,
     pub request_response_information: Option<u8>,
     pub request_problem_information: Option<u8>,
-    pub user_property: Option<Utf8Pair>,
+    pub user_properties: Vec<Utf8Pair>,
     pub authentication_methods: Option<String>,
-    pub authentication_data: Option<String>,
+    pub authentication_data: Option<Bytes>,
 }
 
 impl ConnectProperties {
-    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
-        Ok(Bytes::new())
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(session_expiry_interval) = self.session_expiry_interval {
+            props.put_u8(PropertyIdentifiers::SESSION_EXPIRY_INTERVAL);
+            props.put_u32(session_expiry_interval);
+        }
+        if let Some(receive_maximum) = self.receive_maximum {
+            props.put_u8(PropertyIdentifiers::RECEIVE_MAXIMUM);
+            props.put_u16(receive_maximum);
+        }
+        if let Some(maximum_packet_size) = self.maximum_packet_size {
+            props.put_u8(PropertyIdentifiers::MAXIMUM_PACKET_SIZE);
+            props.put_u32(maximum_packet_size);
+        }
+        if let Some(topic_alias_maximum) = self.topic_alias_maximum {
+            props.put_u8(PropertyIdentifiers::TOPIC_ALIAS_MAXIMUM);
+            props.put_u16(topic_alias_maximum);
+        }
+        if let Some(request_response_information) = self.request_response_information {
+            props.put_u8(PropertyIdentifiers::REQUEST_RESPONSE_INFORMATION);
+            props.put_u8(request_response_information);
+        }
+        if let Some(request_problem_information) = self.request_problem_information {
+            props.put_u8(PropertyIdentifiers::REQUEST_PROBLEM_INFORMATION);
+            props.put_u8(request_problem_information);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        if let Some(authentication_methods) = &self.authentication_methods {
+            props.put_u8(PropertyIdentifiers::AUTHENTICATION_METHOD);
+            props.extend_from_slice(&encode_utf_string(authentication_methods.clone())?);
+        }
+        if let Some(authentication_data) = &self.authentication_data {
+            props.put_u8(PropertyIdentifiers::AUTHENTICATION_DATA);
+            props.extend_from_slice(&encode_binary_data(authentication_data.clone())?);
+        }
+        Ok(props.to_bytes())
     }
 }
 
@@ -33,13 +71,42 @@ pub struct WillProperties {
     pub message_expiry_interval: Option<u32>,
     pub content_type: Option<String>,
     pub response_topic: Option<String>,
-    pub correlation_data: Option<String>,
-    pub user_property: Option<Utf8Pair>,
+    pub correlation_data: Option<Bytes>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
 impl WillProperties {
-    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
-        Ok(Bytes::new())
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(will_delay_interval) = self.will_delay_interval {
+            props.put_u8(PropertyIdentifiers::WILL_DELAY_INTERVAL);
+            props.put_u32(will_delay_interval);
+        }
+        if let Some(payload_format_indicator) = self.payload_format_indicator {
+            props.put_u8(PropertyIdentifiers::PAYLOAD_FORMAT_INDICATOR);
+            props.put_u8(payload_format_indicator);
+        }
+        if let Some(message_expiry_interval) = self.message_expiry_interval {
+            props.put_u8(PropertyIdentifiers::MESSAGE_EXPIRY_INTERVAL);
+            props.put_u32(message_expiry_interval);
+        }
+        if let Some(content_type) = &self.content_type {
+            props.put_u8(PropertyIdentifiers::CONTENT_TYPE);
+            props.extend_from_slice(&encode_utf_string(content_type.clone())?);
+        }
+        if let Some(response_topic) = &self.response_topic {
+            props.put_u8(PropertyIdentifiers::RESPONSE_TOPIC);
+            props.extend_from_slice(&encode_utf_string(response_topic.clone())?);
+        }
+        if let Some(correlation_data) = &self.correlation_data {
+            props.put_u8(PropertyIdentifiers::CORRELATION_DATA);
+            props.extend_from_slice(&encode_binary_data(correlation_data.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
     }
 }
 
@@ -55,8 +122,42 @@ pub struct ConnectPayload {
 
 
 impl ConnectPayload {
-    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
-        Ok(Bytes::new())
+    /// `flags` decides which optional fields are actually present on the wire - the payload's
+    /// own fields stay populated (as `Some`) even when their flag is unset, since that's what
+    /// `Connect::assemble` currently hands back. `is_mqtt5` gates the Will Properties block,
+    /// which MQTT 3.1.1 doesn't have at all.
+    pub(crate) fn disassemble(&self, flags: &ConnectFlags, is_mqtt5: bool) -> Result<Bytes, Error> {
+        let mut payload = BytesMut::new();
+        payload.extend_from_slice(&encode_utf_string(self.client_id.clone())?);
+
+        if flags.will_flag {
+            if is_mqtt5 {
+                let will_props = match &self.will_props {
+                    Some(will_props) => will_props.disassemble()?,
+                    None => Bytes::new(),
+                };
+                payload.extend_from_slice(&encode_variable_byte(will_props.len() as i32)?);
+                payload.extend_from_slice(&will_props);
+            }
+
+            let will_topic = self.will_topic.clone().unwrap_or_default();
+            payload.extend_from_slice(&encode_utf_string(will_topic)?);
+
+            let will_payload = self.will_payload.clone().unwrap_or_default();
+            payload.extend_from_slice(&encode_utf_string(will_payload)?);
+        }
+
+        if flags.username {
+            let username = self.username.clone().unwrap_or_default();
+            payload.extend_from_slice(&encode_utf_string(username)?);
+        }
+
+        if flags.password {
+            let password = self.password.clone().unwrap_or_default();
+            payload.extend_from_slice(&encode_utf_string(password)?);
+        }
+
+        Ok(payload.to_bytes())
     }
 }
 
@@ -73,8 +174,17 @@ pub struct ConnectFlags {
 
 
 impl ConnectFlags {
-    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
-        Ok(Bytes::new())
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let byte = ((self.username as u8) << 7)
+            | ((self.password as u8) << 6)
+            | ((self.will_retain as u8) << 5)
+            | (self.will_qos << 3)
+            | ((self.will_flag as u8) << 2)
+            | ((self.clean_session as u8) << 1);
+
+        let mut flags = BytesMut::new();
+        flags.put_u8(byte);
+        Ok(flags.to_bytes())
     }
 }
 
@@ -124,32 +234,33 @@ impl Connect {
 
 impl Connect {
     pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
-        let mut fixed_header = BytesMut::new();
-        fixed_header.reserve(3);
-        fixed_header.put_u8(1);
-        fixed_header.put_u8(1); // packet len
+        let Protocol::MQTT(level) = self.protocol;
+        let is_mqtt5 = level == 5;
 
         let mut var_header = BytesMut::new();
-        var_header.reserve(1);
-        var_header.put(encode_utf_string(String::from("MQTT"))?);
-        var_header.put_u8(5); // proto version
-        var_header.put(self.flags.disassemble()?);
+        var_header.extend_from_slice(&encode_utf_string(self.proto_name.clone())?);
+        var_header.put_u8(level);
+        var_header.extend_from_slice(&self.flags.disassemble()?);
         var_header.put_u16(self.keep_alive);
 
-        let _ = match self.properties {
-            Some(p) => { var_header.put(p.disassemble()?); }
-            None => ()
-        };
-
+        // MQTT 3.1.1 has no property blocks anywhere in the Connect packet.
+        if is_mqtt5 {
+            let props = match &self.properties {
+                Some(properties) => properties.disassemble()?,
+                None => Bytes::new(),
+            };
+            var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+            var_header.extend_from_slice(&props);
+        }
 
-        let mut payload = BytesMut::new();
-        payload.reserve(1);
-        payload.put(self.payload.disassemble()?);
+        let payload = self.payload.disassemble(&self.flags, is_mqtt5)?;
 
         let mut packet = BytesMut::new();
-        packet.extend(fixed_header);
-        packet.extend(var_header);
-        packet.extend(payload);
+        packet.put_u8(0b0001_0000); // CONNECT, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte((var_header.len() + payload.len()) as i32)?);
+        packet.extend_from_slice(&var_header);
+        packet.extend_from_slice(&payload);
+
         Ok(packet.to_bytes())
     }
 
@@ -162,9 +273,11 @@ impl Connect {
         }
 
         let protocol = match protocol_level {
+            4 => Protocol::MQTT(4),
             5 => Protocol::MQTT(5),
             num => return Err(Error::InvalidProtocolLevel(num)),
         };
+        let is_mqtt5 = protocol_level == 5;
 
         let flag_bytes = bytes.get_u8();
         let keep_alive = bytes.get_u16();
@@ -173,13 +286,20 @@ impl Connect {
             username: flag_bytes & (1 << 0b111) != 0,
             password: flag_bytes & (1 << 0b110) != 0,
             will_retain: flag_bytes & (1 << 0b101) != 0,
-            will_qos: 0,
+            will_qos: (flag_bytes & 0b0001_1000) >> 3,
             will_flag: flag_bytes & (1 << 0b10) != 0,
             clean_session: flag_bytes & (1 << 0b1) != 0,
             reserved: flag_bytes & (1 << 0b0),
         };
 
-        let _props = extract_properties(&mut bytes)?;
+        // [MQTT-3.1.2-3]: the reserved bit must be 0. [MQTT-3.1.2-11]/[MQTT-3.1.2-13]: will_qos
+        // and will_retain must be 0 when the will flag isn't set.
+        if flags.reserved != 0 || (!flags.will_flag && (flags.will_qos != 0 || flags.will_retain)) {
+            return Err(Error::MalformedConnectFlags(flag_bytes));
+        }
+
+        // MQTT 3.1.1 has no property blocks anywhere in the Connect packet.
+        let _props = if is_mqtt5 { extract_properties(&mut bytes, PropertyOwner::Connect)? } else { None };
 
         let conn_props = match _props {
             Some(props) => {
@@ -191,7 +311,7 @@ impl Connect {
                         topic_alias_maximum: props.topic_alias_maximum,
                         request_response_information: props.request_response_info,
                         request_problem_information: props.request_problem_info,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                         authentication_methods: props.authentication_method,
                         authentication_data: props.authentication_data,
                     }
@@ -211,38 +331,40 @@ impl Connect {
             content_type: None,
             response_topic: None,
             correlation_data: None,
-            user_property: None,
+            user_properties: Vec::new(),
         };
         let mut will_topic: String = String::new();
         let mut will_payload: String = String::new();
 
         if flags.will_flag {
-            let _props = extract_properties(&mut bytes)?;
-            let will_prop = match _props {
-                Some(props) => {
-                    WillProperties {
-                        will_delay_interval: props.will_delay_interval,
-                        payload_format_indicator: props.payload_format_indicator,
-                        message_expiry_interval: props.message_expiry_interval,
-                        content_type: props.content_type,
-                        response_topic: props.response_topic,
-                        correlation_data: props.correlation_data,
-                        user_property: props.user_property,
+            // MQTT 3.1.1 has no Will Properties block at all.
+            if is_mqtt5 {
+                let _props = extract_properties(&mut bytes, PropertyOwner::Will)?;
+                will_props = match _props {
+                    Some(props) => {
+                        WillProperties {
+                            will_delay_interval: props.will_delay_interval,
+                            payload_format_indicator: props.payload_format_indicator,
+                            message_expiry_interval: props.message_expiry_interval,
+                            content_type: props.content_type,
+                            response_topic: props.response_topic,
+                            correlation_data: props.correlation_data,
+                            user_properties: props.user_properties,
+                        }
                     }
-                }
-                _ => WillProperties {
-                    will_delay_interval: None,
-                    payload_format_indicator: None,
-                    message_expiry_interval: None,
-                    content_type: None,
-                    response_topic: None,
-                    correlation_data: None,
-                    user_property: None,
-                }
-            };
+                    None => will_props,
+                };
+            }
+
             // below two props will be also present when the will flag is set
             will_topic = extract_mqtt_string(&mut bytes)?;
-            will_payload = extract_mqtt_string(&mut bytes)?;
+            will_payload = if is_mqtt5 {
+                extract_mqtt_string(&mut bytes)?
+            } else {
+                // 3.1.1's Will Message is length-prefixed binary data, not a UTF-8 string.
+                let (data, _len) = decode_binary_data(&mut bytes);
+                String::from_utf8_lossy(&data?).into_owned()
+            };
         }
 
         let mut username: String = String::new();
@@ -363,4 +485,173 @@ mod test_connect {
         );
         Ok(())
     }
+
+    #[test]
+    fn connect_rejects_reserved_bit_set() {
+        let packet_stream = &[
+            0x10, 0x16, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x05, 0x03, 0x00, 0x3c, 0x00,
+            0x00, 0x09, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74, 0x2d, 0x69, 0x64,
+        ];
+        let mut stream = bytes::BytesMut::from(&packet_stream[..]);
+
+        match mqtt_read(&mut stream, 100) {
+            Err(Error::MalformedConnectFlags(0x03)) => {}
+            res => panic!("Expected MalformedConnectFlags, got = {:?}", res),
+        }
+    }
+
+    #[test]
+    fn connect_rejects_will_qos_set_without_will_flag() {
+        let packet_stream = &[
+            0x10, 0x16, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x05, 0x0A, 0x00, 0x3c, 0x00,
+            0x00, 0x09, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74, 0x2d, 0x69, 0x64,
+        ];
+        let mut stream = bytes::BytesMut::from(&packet_stream[..]);
+
+        match mqtt_read(&mut stream, 100) {
+            Err(Error::MalformedConnectFlags(0x0A)) => {}
+            res => panic!("Expected MalformedConnectFlags, got = {:?}", res),
+        }
+    }
+
+    #[test]
+    fn connect_3_1_1_read_works_correctly() {
+        let packet_stream = &[
+            0x10, 0x15, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x04, 0x02, 0x00, 0x3c, 0x00,
+            0x09, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74, 0x2d, 0x69, 0x64,
+        ];
+
+        let mut stream = bytes::BytesMut::from(&packet_stream[..]);
+        let packet = mqtt_read(&mut stream, 100).unwrap();
+        let packet = match packet {
+            Packet::Connect(connect) => connect,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(packet.protocol, Protocol::MQTT(4));
+        assert_eq!(packet.keep_alive, 60);
+        assert_eq!(packet.payload.client_id, "client-id");
+        assert_eq!(packet.flags.clean_session, true);
+        assert_eq!(packet.properties, None);
+    }
+
+    #[test]
+    fn connect_3_1_1_write_and_read_round_trips_with_binary_will() -> Result<(), Error> {
+        let conn = Connect {
+            protocol: Protocol::MQTT(4),
+            proto_name: String::from("MQTT"),
+            keep_alive: 60,
+            flags: ConnectFlags {
+                username: false,
+                password: false,
+                will_retain: false,
+                will_flag: true,
+                will_qos: 0,
+                clean_session: true,
+                reserved: 0,
+            },
+            properties: None,
+            payload: ConnectPayload {
+                client_id: String::from("client-id"),
+                will_props: None,
+                will_topic: Some(String::from("a/will")),
+                will_payload: Some(String::from("bye")),
+                username: None,
+                password: None,
+            },
+        };
+
+        let conn_stream = mqtt_write(Packet::Connect(conn))?;
+        let mut stream = BytesMut::from(&conn_stream[..]);
+
+        let packet = mqtt_read(&mut stream, 100).unwrap();
+        let packet = match packet {
+            Packet::Connect(connect) => connect,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(packet.protocol, Protocol::MQTT(4));
+        assert_eq!(packet.properties, None);
+        assert_eq!(packet.payload.will_topic, Some(String::from("a/will")));
+        assert_eq!(packet.payload.will_payload, Some(String::from("bye")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn connect_write_and_read_round_trips_with_will_and_auth_and_properties() -> Result<(), Error> {
+        let conn = Connect {
+            protocol: Protocol::MQTT(5),
+            proto_name: String::from("MQTT"),
+            keep_alive: 30,
+            flags: ConnectFlags {
+                username: true,
+                password: true,
+                will_retain: true,
+                will_flag: true,
+                will_qos: 1,
+                clean_session: false,
+                reserved: 0,
+            },
+            properties: Some(ConnectProperties {
+                session_expiry_interval: Some(120),
+                receive_maximum: Some(10),
+                maximum_packet_size: None,
+                topic_alias_maximum: None,
+                request_response_information: None,
+                request_problem_information: None,
+                user_properties: vec![("k".to_owned(), "v".to_owned())],
+                authentication_methods: Some("PLAIN".to_owned()),
+                authentication_data: None,
+            }),
+            payload: ConnectPayload {
+                client_id: String::from("client-id"),
+                will_props: Some(WillProperties {
+                    will_delay_interval: Some(5),
+                    payload_format_indicator: None,
+                    message_expiry_interval: None,
+                    content_type: None,
+                    response_topic: None,
+                    correlation_data: None,
+                    user_properties: Vec::new(),
+                }),
+                will_topic: Some(String::from("a/will")),
+                will_payload: Some(String::from("bye")),
+                username: Some(String::from("user")),
+                password: Some(String::from("pass")),
+            },
+        };
+
+        let conn_stream = mqtt_write(Packet::Connect(conn))?;
+        let mut stream = BytesMut::from(&conn_stream[..]);
+
+        let packet = mqtt_read(&mut stream, 1024).unwrap();
+        let packet = match packet {
+            Packet::Connect(connect) => connect,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(packet.keep_alive, 30);
+        assert_eq!(packet.payload.client_id, "client-id");
+        assert_eq!(packet.flags.username, true);
+        assert_eq!(packet.flags.password, true);
+        assert_eq!(packet.flags.will_retain, true);
+        assert_eq!(packet.flags.will_flag, true);
+        assert_eq!(packet.flags.clean_session, false);
+        assert_eq!(packet.flags.will_qos, 1);
+        assert_eq!(packet.payload.will_topic, Some(String::from("a/will")));
+        assert_eq!(packet.payload.will_payload, Some(String::from("bye")));
+        assert_eq!(packet.payload.username, Some(String::from("user")));
+        assert_eq!(packet.payload.password, Some(String::from("pass")));
+        assert_eq!(
+            packet.properties.as_ref().unwrap().session_expiry_interval,
+            Some(120)
+        );
+        assert_eq!(
+            packet.properties.as_ref().unwrap().authentication_methods,
+            Some(String::from("PLAIN"))
+        );
+
+        Ok(())
+    }
 }
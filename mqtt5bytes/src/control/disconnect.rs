@@ -1,41 +1,124 @@
-use crate::{Error, Utf8Pair};
+use crate::{checked_get_u8, encode_utf_string, encode_utf_string_pair, encode_variable_byte, disconnect_reason_code, DisconnectReason, Error, Utf8Pair};
 use crate::FixedHeader;
 
-use bytes::Bytes;
-use crate::control::properties::extract_properties;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
 use alloc::string::String;
+use alloc::vec::Vec;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DisconnectProperties {
     pub session_expiry_interval: Option<u32>,
     pub reason_string: Option<String>,
-    pub user_property: Option<Utf8Pair>,
+    pub user_properties: Vec<Utf8Pair>,
     pub server_reference: Option<String>,
 }
 
+impl DisconnectProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(session_expiry_interval) = self.session_expiry_interval {
+            props.put_u8(PropertyIdentifiers::SESSION_EXPIRY_INTERVAL);
+            props.put_u32(session_expiry_interval);
+        }
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        if let Some(server_reference) = &self.server_reference {
+            props.put_u8(PropertyIdentifiers::SERVER_INFO);
+            props.extend_from_slice(&encode_utf_string(server_reference.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Disconnect {
+    pub reason_code: DisconnectReason,
     pub properties: Option<DisconnectProperties>
 }
 
 impl Disconnect {
     pub(crate) fn assemble(fixed_header: FixedHeader, mut bytes: Bytes) -> Result<Self, Error> {
-        let _props = extract_properties(&mut bytes)?;
+        let variable_header_index = fixed_header.header_len;
+        bytes.advance(variable_header_index);
+
+        // `read.rs` never hands a zero-`remaining_len` DISCONNECT to `assemble` (only PINGREQ/
+        // PINGRESP may omit their variable header entirely), so the Reason Code byte is always
+        // present here even though the spec allows omitting it for a plain Normal Disconnection.
+        let reason_code = disconnect_reason_code(checked_get_u8(&mut bytes)?)?;
+
+        let _props = if fixed_header.remaining_len > 1 {
+            extract_properties(&mut bytes, PropertyOwner::Disconnect)?
+        } else {
+            None
+        };
         let disconnect = match _props {
             Some(props) => {
                 let properties = Some(
                     DisconnectProperties {
                         session_expiry_interval: props.session_expiry_interval,
                         reason_string: props.reason_string,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                         server_reference: props.server_info,
                     }
                 );
-                Disconnect { properties }
+                Disconnect { reason_code, properties }
             }
-            None => Disconnect { properties: None }
+            None => Disconnect { reason_code, properties: None }
         };
         Ok(disconnect)
     }
 }
+
+impl Disconnect {
+    pub fn new(reason_code: DisconnectReason, properties: Option<DisconnectProperties>) -> Disconnect {
+        Disconnect { reason_code, properties }
+    }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u8(self.reason_code as u8);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b1110_0000); // DISCONNECT, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte(var_header.len() as i32)?);
+        packet.extend_from_slice(&var_header);
+
+        Ok(packet.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test_disconnect {
+    use crate::*;
+    use bytes::BytesMut;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn disconnect_write_and_read_round_trips() {
+        let disconnect = Disconnect::new(DisconnectReason::ServerShuttingDown, None);
+        let mut stream = BytesMut::from(mqtt_write(Packet::Disconnect(disconnect.clone())).unwrap().as_ref());
+
+        let packet = mqtt_read(&mut stream, 1024).unwrap();
+        let packet = match packet {
+            Packet::Disconnect(packet) => packet,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(packet, disconnect);
+    }
+}
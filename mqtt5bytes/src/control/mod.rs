@@ -28,7 +28,7 @@ pub use self::subscribe::*;
 pub use self::unsuback::*;
 pub use self::unsubscribe::*;
 
-use crate::{Error, QoS};
+use crate::{ConnAckReason, DisconnectReason, Error, PubAckReason, QoS, RetainHandling, SubscribeReasonCode};
 use alloc::string::String;
 use bytes::{Buf, Bytes, BytesMut};
 use alloc::vec::Vec;
@@ -42,9 +42,146 @@ pub(crate) fn qos(num: u8) -> Result<QoS, Error> {
     }
 }
 
+/// Decode the 2-bit Retain Handling value packed into a SUBSCRIBE options byte.
+pub(crate) fn retain_handling(num: u8) -> Result<RetainHandling, Error> {
+    match num {
+        0 => Ok(RetainHandling::SendAtSubscribe),
+        1 => Ok(RetainHandling::SendAtSubscribeIfNew),
+        2 => Ok(RetainHandling::DoNotSend),
+        num => Err(Error::InvalidRetainHandling(num)),
+    }
+}
+
+/// Decode a SUBACK/UNSUBACK per-topic-filter reason code byte.
+pub(crate) fn subscribe_reason_code(code: u8) -> Result<SubscribeReasonCode, Error> {
+    match code {
+        0x00 => Ok(SubscribeReasonCode::GrantedQoS0),
+        0x01 => Ok(SubscribeReasonCode::GrantedQoS1),
+        0x02 => Ok(SubscribeReasonCode::GrantedQoS2),
+        0x80 => Ok(SubscribeReasonCode::UnspecifiedError),
+        0x83 => Ok(SubscribeReasonCode::ImplementationSpecificError),
+        0x87 => Ok(SubscribeReasonCode::NotAuthorized),
+        0x8F => Ok(SubscribeReasonCode::TopicFilterInvalid),
+        0x91 => Ok(SubscribeReasonCode::PacketIdentifierInUse),
+        0x97 => Ok(SubscribeReasonCode::QuotaExceeded),
+        0x9E => Ok(SubscribeReasonCode::SharedSubscriptionsNotSupported),
+        0xA1 => Ok(SubscribeReasonCode::SubscriptionIdentifiersNotSupported),
+        0xA2 => Ok(SubscribeReasonCode::WildcardSubscriptionsNotSupported),
+        code => Err(Error::InvalidSubscribeReasonCode(code)),
+    }
+}
+
+/// Decode a CONNACK reason code byte.
+pub(crate) fn connack_reason_code(code: u8) -> Result<ConnAckReason, Error> {
+    match code {
+        0x00 => Ok(ConnAckReason::Success),
+        0x80 => Ok(ConnAckReason::UnspecifiedError),
+        0x81 => Ok(ConnAckReason::MalformedPacket),
+        0x82 => Ok(ConnAckReason::ProtocolError),
+        0x83 => Ok(ConnAckReason::ImplementationSpecificError),
+        0x84 => Ok(ConnAckReason::UnsupportedProtocolVersion),
+        0x85 => Ok(ConnAckReason::ClientIdentifierNotValid),
+        0x86 => Ok(ConnAckReason::BadUserNameOrPassword),
+        0x87 => Ok(ConnAckReason::NotAuthorized),
+        0x88 => Ok(ConnAckReason::ServerUnavailable),
+        0x89 => Ok(ConnAckReason::ServerBusy),
+        0x8A => Ok(ConnAckReason::Banned),
+        0x8C => Ok(ConnAckReason::BadAuthenticationMethod),
+        0x90 => Ok(ConnAckReason::TopicNameInvalid),
+        0x95 => Ok(ConnAckReason::PacketTooLarge),
+        0x97 => Ok(ConnAckReason::QuotaExceeded),
+        0x99 => Ok(ConnAckReason::PayloadFormatInvalid),
+        0x9A => Ok(ConnAckReason::RetainNotSupported),
+        0x9B => Ok(ConnAckReason::QoSNotSupported),
+        0x9C => Ok(ConnAckReason::UseAnotherServer),
+        0x9D => Ok(ConnAckReason::ServerMoved),
+        0x9F => Ok(ConnAckReason::ConnectionRateExceeded),
+        code => Err(Error::InvalidConnectReturnCode(code)),
+    }
+}
+
+/// Decode a PUBACK reason code byte.
+pub(crate) fn puback_reason_code(code: u8) -> Result<PubAckReason, Error> {
+    match code {
+        0x00 => Ok(PubAckReason::Success),
+        0x10 => Ok(PubAckReason::NoMatchingSubscribers),
+        0x80 => Ok(PubAckReason::UnspecifiedError),
+        0x83 => Ok(PubAckReason::ImplementationSpecificError),
+        0x87 => Ok(PubAckReason::NotAuthorized),
+        0x90 => Ok(PubAckReason::TopicNameInvalid),
+        0x91 => Ok(PubAckReason::PacketIdentifierInUse),
+        0x97 => Ok(PubAckReason::QuotaExceeded),
+        0x99 => Ok(PubAckReason::PayloadFormatInvalid),
+        code => Err(Error::InvalidPubAckReasonCode(code)),
+    }
+}
+
+/// Decode a DISCONNECT reason code byte.
+pub(crate) fn disconnect_reason_code(code: u8) -> Result<DisconnectReason, Error> {
+    match code {
+        0x00 => Ok(DisconnectReason::NormalDisconnection),
+        0x04 => Ok(DisconnectReason::DisconnectWithWillMessage),
+        0x80 => Ok(DisconnectReason::UnspecifiedError),
+        0x81 => Ok(DisconnectReason::MalformedPacket),
+        0x82 => Ok(DisconnectReason::ProtocolError),
+        0x83 => Ok(DisconnectReason::ImplementationSpecificError),
+        0x87 => Ok(DisconnectReason::NotAuthorized),
+        0x89 => Ok(DisconnectReason::ServerBusy),
+        0x8B => Ok(DisconnectReason::ServerShuttingDown),
+        0x8D => Ok(DisconnectReason::KeepAliveTimeout),
+        0x8E => Ok(DisconnectReason::SessionTakenOver),
+        0x8F => Ok(DisconnectReason::TopicFilterInvalid),
+        0x90 => Ok(DisconnectReason::TopicNameInvalid),
+        0x93 => Ok(DisconnectReason::ReceiveMaximumExceeded),
+        0x94 => Ok(DisconnectReason::TopicAliasInvalid),
+        0x95 => Ok(DisconnectReason::PacketTooLarge),
+        0x96 => Ok(DisconnectReason::MessageRateTooHigh),
+        0x97 => Ok(DisconnectReason::QuotaExceeded),
+        0x98 => Ok(DisconnectReason::AdministrativeAction),
+        0x99 => Ok(DisconnectReason::PayloadFormatInvalid),
+        0x9A => Ok(DisconnectReason::RetainNotSupported),
+        0x9B => Ok(DisconnectReason::QoSNotSupported),
+        0x9C => Ok(DisconnectReason::UseAnotherServer),
+        0x9D => Ok(DisconnectReason::ServerMoved),
+        0x9E => Ok(DisconnectReason::SharedSubscriptionsNotSupported),
+        0x9F => Ok(DisconnectReason::ConnectionRateExceeded),
+        0xA0 => Ok(DisconnectReason::MaximumConnectTime),
+        0xA1 => Ok(DisconnectReason::SubscriptionIdentifiersNotSupported),
+        0xA2 => Ok(DisconnectReason::WildcardSubscriptionsNotSupported),
+        code => Err(Error::InvalidDisconnectReasonCode(code)),
+    }
+}
+
+/// Bounds-checked single byte read. The whole packet is always fully buffered before any
+/// `assemble`/`extract_*` function runs (see `mqtt_read`/`mqtt_read_incremental`), so running out
+/// of bytes here means a length field inside the packet lied about its own size, not that more
+/// data is still in flight - that's a malformed packet, hence `Err` rather than `Ok(None)`.
+pub(crate) fn checked_get_u8(stream: &mut Bytes) -> Result<u8, Error> {
+    if stream.remaining() < 1 {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(stream.get_u8())
+}
+
+/// Bounds-checked 2-byte read. See [`checked_get_u8`].
+pub(crate) fn checked_get_u16(stream: &mut Bytes) -> Result<u16, Error> {
+    if stream.remaining() < 2 {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(stream.get_u16())
+}
+
+/// Bounds-checked 4-byte read. See [`checked_get_u8`].
+pub(crate) fn checked_get_u32(stream: &mut Bytes) -> Result<u32, Error> {
+    if stream.remaining() < 4 {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(stream.get_u32())
+}
+
 // extract methods
 pub(crate) fn extract_mqtt_string(stream: &mut Bytes) -> Result<String, Error> {
-    let len = stream.get_u16() as usize;
+    let len = checked_get_u16(stream)? as usize;
     // Invalid control which reached this point (simulated invalid control actually triggered this)
     // should not cause the split to cross boundaries
     if len > stream.len() {
@@ -78,7 +215,11 @@ pub(crate) fn decode_variable_byte(stream: &mut Bytes) -> (Result<u32, Error>, u
     let mut byte_len = 0;
     let mut encoded_byte = 128;
     while (encoded_byte & 128) != 0 {
-        encoded_byte = stream.get_u8() as u32;
+        let byte = match checked_get_u8(stream) {
+            Ok(byte) => byte,
+            Err(e) => return (Err(e), byte_len),
+        };
+        encoded_byte = byte as u32;
         byte_len += ByteLengths::BYTE_INT;
         value += (encoded_byte & 127) * multiplier;
         if multiplier > 128*128*128 {
@@ -94,17 +235,17 @@ pub(crate) fn decode_variable_byte(stream: &mut Bytes) -> (Result<u32, Error>, u
 /// Convert an integer 0 <= x <= 268435455 into multi-byte format.
 ///  returns the buffer converted from the integer.
 pub(crate) fn encode_variable_byte(mut value: i32) -> Result<Bytes, Error> {
-    let mut encoded_byte = 0;
     let mut buf = BytesMut::new();
-    while value > 0 {
-        encoded_byte = value % 128;
+    loop {
+        let mut encoded_byte = (value % 128) as u8;
         value /= 128;
         if value > 0 {
             encoded_byte |= 128;
         }
-        buf.extend_from_slice(
-            encoded_byte.to_ne_bytes().as_ref()
-        );
+        buf.extend_from_slice(&[encoded_byte]);
+        if value <= 0 {
+            break;
+        }
     }
     return Ok(Bytes::from(buf));
 }
@@ -112,18 +253,26 @@ pub(crate) fn encode_variable_byte(mut value: i32) -> Result<Bytes, Error> {
 /// decode utf-8 string defined in MQTT v5.0 spec
 /// returns the decoded utf-8 string and the length of bytes decoded
 pub(crate) fn decode_utf_string(stream: &mut Bytes) -> (Result<String, Error>, u32) {
-    let mut strlen = stream.get_u16();
+    let mut strlen = match checked_get_u16(stream) {
+        Ok(strlen) => strlen,
+        Err(e) => return (Err(e), 0),
+    };
     let bytelen = ByteLengths::TWO_BYTE_INT + strlen as u32; // as two bytes have been read above for strlen
     let mut data: Vec<u8> = Vec::new();
     while strlen != 0 {
-        data.extend_from_slice(&[stream.get_u8()]);
+        let byte = match checked_get_u8(stream) {
+            Ok(byte) => byte,
+            Err(e) => return (Err(e), bytelen),
+        };
+        data.extend_from_slice(&[byte]);
+        strlen -= 1;
     }
     let decoded = String::from_utf8(data);
     match decoded {
         Ok(val) => {
             (Ok(val), bytelen)
         }
-        Err(utf_err) => {
+        Err(_utf_err) => {
             (Err(Error::UnexpectedEof), bytelen)
         }
     }
@@ -132,9 +281,9 @@ pub(crate) fn decode_utf_string(stream: &mut Bytes) -> (Result<String, Error>, u
 /// encode utf-8 string defined in MQTT v5.0 spec
 /// returns the encoded utf-8 string as bytes that can be added to MQTT stream.
 pub(crate) fn encode_utf_string(value: String) -> Result<Bytes, Error> {
-    let mut buf = BytesMut::from("");
+    let mut buf = BytesMut::new();
     let bts = value.as_bytes();
-    let blen = bts.len().to_ne_bytes();
+    let blen = (bts.len() as u16).to_be_bytes();
     buf.extend_from_slice(blen.as_ref()); // 2 byte len + characters
     buf.extend_from_slice(bts.as_ref());
     return Ok(buf.to_bytes());
@@ -143,10 +292,17 @@ pub(crate) fn encode_utf_string(value: String) -> Result<Bytes, Error> {
 /// decode utf-8 string pair defined in MQTT v5.0 spec
 /// returns the decoded utf-8 string pair and the length of bytes decoded
 pub(crate) fn decode_utf_string_pair(stream: &mut Bytes) -> (Result<Utf8Pair, Error>, u32) {
-    let (K, l1) = decode_utf_string(stream);
-    let (V, l2) = decode_utf_string(stream);
-    let (k, v) = (K.unwrap(), V.unwrap());
-    (Ok((k, v)), l1 + l2)
+    let (key, l1) = decode_utf_string(stream);
+    let key = match key {
+        Ok(key) => key,
+        Err(e) => return (Err(e), l1),
+    };
+    let (value, l2) = decode_utf_string(stream);
+    let value = match value {
+        Ok(value) => value,
+        Err(e) => return (Err(e), l1 + l2),
+    };
+    (Ok((key, value)), l1 + l2)
 }
 
 /// encode utf-8 string pair defined in MQTT v5.0 spec
@@ -154,10 +310,10 @@ pub(crate) fn decode_utf_string_pair(stream: &mut Bytes) -> (Result<Utf8Pair, Er
 pub(crate) fn encode_utf_string_pair(value: Utf8Pair) -> Result<Bytes, Error> {
     let (k, v) = value;
     let mut data = BytesMut::new();
-    let (klen, vlen) = (k.len() as i16, v.len() as i16);
-    data.extend_from_slice(klen.to_ne_bytes().as_ref());
+    let (klen, vlen) = (k.len() as u16, v.len() as u16);
+    data.extend_from_slice(klen.to_be_bytes().as_ref());
     data.extend_from_slice(k.as_bytes());
-    data.extend_from_slice(vlen.to_ne_bytes().as_ref());
+    data.extend_from_slice(vlen.to_be_bytes().as_ref());
     data.extend_from_slice(v.as_bytes());
     Ok(data.to_bytes())
 }
@@ -166,11 +322,18 @@ pub(crate) fn encode_utf_string_pair(value: Utf8Pair) -> Result<Bytes, Error> {
 /// returns the decoded binary data and the length of bytes decoded
 pub(crate) fn decode_binary_data(stream: &mut Bytes) -> (Result<Bytes, Error>, u32)
 {
-    let mut blen = stream.get_u16();
+    let mut blen = match checked_get_u16(stream) {
+        Ok(blen) => blen,
+        Err(e) => return (Err(e), 0),
+    };
     let mut data = BytesMut::new();
     while blen != 0 {
+        let byte = match checked_get_u8(stream) {
+            Ok(byte) => byte,
+            Err(e) => return (Err(e), ByteLengths::TWO_BYTE_INT + data.len() as u32),
+        };
         blen -= 1;
-        data.extend_from_slice(stream.get_u8().to_ne_bytes().as_ref())
+        data.extend_from_slice(byte.to_ne_bytes().as_ref())
     }
 
     (Ok(data.to_bytes()), ByteLengths::TWO_BYTE_INT + data.len() as u32)
@@ -179,9 +342,9 @@ pub(crate) fn decode_binary_data(stream: &mut Bytes) -> (Result<Bytes, Error>, u
 /// encode binary data defined in MQTT v5.0 spec
 /// returns the binary data string and length of bytes encoded.
 pub(crate) fn encode_binary_data(value: Bytes) -> Result<Bytes, Error> {
-    let blen = value.len() as i16;
+    let blen = value.len() as u16;
     let mut data = BytesMut::new();
-    data.extend_from_slice(blen.to_ne_bytes().as_ref());
+    data.extend_from_slice(blen.to_be_bytes().as_ref());
     data.extend_from_slice(value.as_ref());
     Ok(data.to_bytes())
 }
@@ -211,9 +374,18 @@ mod test_decoding {
         let res = encode_variable_byte(912);
         match res {
             Ok(raw) => {
-                assert_eq!(raw, Bytes::from("lal"));
+                assert_eq!(raw, Bytes::from(&[0x90, 0x07][..]));
             }
             _ => {}
         }
     }
+
+    #[test]
+    fn decode_variable_byte_errors_instead_of_panicking_on_an_empty_stream() {
+        let mut stream = Bytes::new();
+        match decode_variable_byte(&mut stream) {
+            (Err(Error::UnexpectedEof), 0) => {}
+            other => panic!("Expected (UnexpectedEof, 0), got = {:?}", other),
+        }
+    }
 }
\ No newline at end of file
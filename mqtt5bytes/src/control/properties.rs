@@ -1,6 +1,6 @@
-use bytes::{Bytes, Buf, BytesMut};
+use bytes::{Bytes, Buf, BytesMut, BufMut};
 use alloc::string::String;
-use crate::{Error, ByteLengths, decode_variable_byte, decode_utf_string, Utf8Pair, decode_utf_string_pair};
+use crate::{Error, ByteLengths, decode_variable_byte, decode_utf_string, encode_utf_string, Utf8Pair, decode_utf_string_pair, encode_utf_string_pair, checked_get_u8, checked_get_u16, checked_get_u32, decode_binary_data, encode_binary_data};
 use alloc::vec::Vec;
 
 pub(crate) struct PropertyIdentifiers;
@@ -36,19 +36,333 @@ impl PropertyIdentifiers {
     pub const SHARED_SUBSCRIPTION_AVAILABLE: u8 = 42;
 }
 
+/// A single MQTT v5 property, keyed by its identifier and carrying a correctly typed value.
+///
+/// Unlike [`Properties`], a `Vec<Property>` preserves the order properties were seen in and
+/// allows `UserProperty` to repeat, since the spec explicitly permits multiple User Property
+/// pairs on the same packet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    PayloadFormatIndicator(u8),
+    MessageExpiryInterval(u32),
+    ContentType(String),
+    ResponseTopic(String),
+    CorrelationData(Bytes),
+    SubscriptionIdentifier(u32),
+    SessionExpiryInterval(u32),
+    AssignedClientIdentifier(String),
+    ServerKeepAlive(u16),
+    AuthenticationMethod(String),
+    AuthenticationData(Bytes),
+    RequestProblemInformation(u8),
+    WillDelayInterval(u32),
+    RequestResponseInformation(u8),
+    ResponseInformation(String),
+    ServerInformation(String),
+    ReasonString(String),
+    ReceiveMaximum(u16),
+    TopicAliasMaximum(u16),
+    TopicAlias(u16),
+    MaximumQoS(u8),
+    RetainAvailable(u8),
+    UserProperty(Utf8Pair),
+    MaximumPacketSize(u32),
+    WildcardSubscriptionAvailable(u8),
+    SubscriptionIdentifierAvailable(u8),
+    SharedSubscriptionAvailable(u8),
+}
+
+/// Decode a property block's identifier+value pairs into an ordered, duplicate-preserving list.
+/// Returns `None` when the block's length prefix is zero (i.e. no properties are present).
+///
+/// Every read here is bounds-checked (`checked_get_u8`/`u16`/`u32`, `decode_utf_string`, ...) and
+/// returns `Err(Error::UnexpectedEof)` rather than panicking on a short buffer. Since callers only
+/// ever hand this a fully-buffered packet (`mqtt_read`/`mqtt_read_incremental` don't split one off
+/// until every byte the fixed header promises has arrived), a short read here always means the
+/// property length prefix itself was wrong, not that more bytes are still in flight.
+pub(crate) fn decode_properties(stream: &mut Bytes) -> Result<Option<Vec<Property>>, Error> {
+    let (_prop_length, _read) = decode_variable_byte(stream);
+    let mut prop_length = _prop_length?;
+    if prop_length == 0 {
+        return Ok(None);
+    }
+
+    let mut properties = Vec::new();
+    while prop_length > 0 {
+        let ident = checked_get_u8(stream)?;
+        prop_length -= ByteLengths::BYTE_INT;
+
+        match ident {
+            PropertyIdentifiers::PAYLOAD_FORMAT_INDICATOR => {
+                properties.push(Property::PayloadFormatIndicator(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::MESSAGE_EXPIRY_INTERVAL => {
+                properties.push(Property::MessageExpiryInterval(checked_get_u32(stream)?));
+                prop_length -= ByteLengths::FOUR_BYTE_INT;
+            }
+            PropertyIdentifiers::CONTENT_TYPE => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::ContentType(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::RESPONSE_TOPIC => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::ResponseTopic(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::CORRELATION_DATA => {
+                let (data, len) = decode_binary_data(stream);
+                properties.push(Property::CorrelationData(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER => {
+                // Unlike most properties this is a variable byte integer (1-4 bytes), not a
+                // fixed 4-byte int - [MQTT-3.3.4-6] also forbids it ever being 0.
+                let (value, read) = decode_variable_byte(stream);
+                let value = value?;
+                if value == 0 {
+                    return Err(Error::InvalidProperty);
+                }
+                properties.push(Property::SubscriptionIdentifier(value));
+                prop_length -= read;
+            }
+            PropertyIdentifiers::SESSION_EXPIRY_INTERVAL => {
+                properties.push(Property::SessionExpiryInterval(checked_get_u32(stream)?));
+                prop_length -= ByteLengths::FOUR_BYTE_INT;
+            }
+            PropertyIdentifiers::ASSIGNED_CLIENT_IDENTIFIER => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::AssignedClientIdentifier(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::SERVER_KEEP_ALIVE => {
+                properties.push(Property::ServerKeepAlive(checked_get_u16(stream)?));
+                prop_length -= ByteLengths::TWO_BYTE_INT;
+            }
+            PropertyIdentifiers::AUTHENTICATION_METHOD => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::AuthenticationMethod(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::AUTHENTICATION_DATA => {
+                let (data, len) = decode_binary_data(stream);
+                properties.push(Property::AuthenticationData(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::REQUEST_PROBLEM_INFORMATION => {
+                properties.push(Property::RequestProblemInformation(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::WILL_DELAY_INTERVAL => {
+                properties.push(Property::WillDelayInterval(checked_get_u32(stream)?));
+                prop_length -= ByteLengths::FOUR_BYTE_INT;
+            }
+            PropertyIdentifiers::REQUEST_RESPONSE_INFORMATION => {
+                properties.push(Property::RequestResponseInformation(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::RESPONSE_INFO => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::ResponseInformation(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::SERVER_INFO => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::ServerInformation(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::REASON_STRING => {
+                let (data, len) = decode_utf_string(stream);
+                properties.push(Property::ReasonString(data?));
+                prop_length -= len;
+            }
+            PropertyIdentifiers::RECEIVE_MAXIMUM => {
+                properties.push(Property::ReceiveMaximum(checked_get_u16(stream)?));
+                prop_length -= ByteLengths::TWO_BYTE_INT;
+            }
+            PropertyIdentifiers::TOPIC_ALIAS_MAXIMUM => {
+                properties.push(Property::TopicAliasMaximum(checked_get_u16(stream)?));
+                prop_length -= ByteLengths::TWO_BYTE_INT;
+            }
+            PropertyIdentifiers::TOPIC_ALIAS => {
+                properties.push(Property::TopicAlias(checked_get_u16(stream)?));
+                prop_length -= ByteLengths::TWO_BYTE_INT;
+            }
+            PropertyIdentifiers::MAXIMUM_QOS => {
+                properties.push(Property::MaximumQoS(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::RETAIN_AVAILABLE => {
+                properties.push(Property::RetainAvailable(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::USER_PROPERTY => {
+                let (data, len) = decode_utf_string_pair(stream);
+                properties.push(Property::UserProperty(data?));
+                prop_length -= len
+            }
+            PropertyIdentifiers::MAXIMUM_PACKET_SIZE => {
+                properties.push(Property::MaximumPacketSize(checked_get_u32(stream)?));
+                prop_length -= ByteLengths::FOUR_BYTE_INT;
+            }
+            PropertyIdentifiers::WILDCARD_SUBSCRIPTION_AVAILABLE => {
+                properties.push(Property::WildcardSubscriptionAvailable(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER_AVAILABLE => {
+                properties.push(Property::SubscriptionIdentifierAvailable(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            PropertyIdentifiers::SHARED_SUBSCRIPTION_AVAILABLE => {
+                properties.push(Property::SharedSubscriptionAvailable(checked_get_u8(stream)?));
+                prop_length -= ByteLengths::BYTE_INT;
+            }
+            _ => {
+                return Err(Error::InvalidProperty);
+            }
+        }
+    }
+
+    Ok(Some(properties))
+}
+
+/// Encode an ordered list of properties back into identifier+value bytes.
+/// Does not prepend the variable-byte property length; callers add that themselves,
+/// matching the convention used by every `*Properties::disassemble`.
+pub(crate) fn encode_properties(properties: &[Property]) -> Result<Bytes, Error> {
+    let mut buf = BytesMut::new();
+    for property in properties {
+        match property {
+            Property::PayloadFormatIndicator(v) => {
+                buf.put_u8(PropertyIdentifiers::PAYLOAD_FORMAT_INDICATOR);
+                buf.put_u8(*v);
+            }
+            Property::MessageExpiryInterval(v) => {
+                buf.put_u8(PropertyIdentifiers::MESSAGE_EXPIRY_INTERVAL);
+                buf.put_u32(*v);
+            }
+            Property::ContentType(v) => {
+                buf.put_u8(PropertyIdentifiers::CONTENT_TYPE);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::ResponseTopic(v) => {
+                buf.put_u8(PropertyIdentifiers::RESPONSE_TOPIC);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::CorrelationData(v) => {
+                buf.put_u8(PropertyIdentifiers::CORRELATION_DATA);
+                buf.extend_from_slice(&encode_binary_data(v.clone())?);
+            }
+            Property::SubscriptionIdentifier(v) => {
+                buf.put_u8(PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER);
+                buf.extend_from_slice(&crate::encode_variable_byte(*v as i32)?);
+            }
+            Property::SessionExpiryInterval(v) => {
+                buf.put_u8(PropertyIdentifiers::SESSION_EXPIRY_INTERVAL);
+                buf.put_u32(*v);
+            }
+            Property::AssignedClientIdentifier(v) => {
+                buf.put_u8(PropertyIdentifiers::ASSIGNED_CLIENT_IDENTIFIER);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::ServerKeepAlive(v) => {
+                buf.put_u8(PropertyIdentifiers::SERVER_KEEP_ALIVE);
+                buf.put_u16(*v);
+            }
+            Property::AuthenticationMethod(v) => {
+                buf.put_u8(PropertyIdentifiers::AUTHENTICATION_METHOD);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::AuthenticationData(v) => {
+                buf.put_u8(PropertyIdentifiers::AUTHENTICATION_DATA);
+                buf.extend_from_slice(&encode_binary_data(v.clone())?);
+            }
+            Property::RequestProblemInformation(v) => {
+                buf.put_u8(PropertyIdentifiers::REQUEST_PROBLEM_INFORMATION);
+                buf.put_u8(*v);
+            }
+            Property::WillDelayInterval(v) => {
+                buf.put_u8(PropertyIdentifiers::WILL_DELAY_INTERVAL);
+                buf.put_u32(*v);
+            }
+            Property::RequestResponseInformation(v) => {
+                buf.put_u8(PropertyIdentifiers::REQUEST_RESPONSE_INFORMATION);
+                buf.put_u8(*v);
+            }
+            Property::ResponseInformation(v) => {
+                buf.put_u8(PropertyIdentifiers::RESPONSE_INFO);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::ServerInformation(v) => {
+                buf.put_u8(PropertyIdentifiers::SERVER_INFO);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::ReasonString(v) => {
+                buf.put_u8(PropertyIdentifiers::REASON_STRING);
+                buf.extend_from_slice(&encode_utf_string(v.clone())?);
+            }
+            Property::ReceiveMaximum(v) => {
+                buf.put_u8(PropertyIdentifiers::RECEIVE_MAXIMUM);
+                buf.put_u16(*v);
+            }
+            Property::TopicAliasMaximum(v) => {
+                buf.put_u8(PropertyIdentifiers::TOPIC_ALIAS_MAXIMUM);
+                buf.put_u16(*v);
+            }
+            Property::TopicAlias(v) => {
+                buf.put_u8(PropertyIdentifiers::TOPIC_ALIAS);
+                buf.put_u16(*v);
+            }
+            Property::MaximumQoS(v) => {
+                buf.put_u8(PropertyIdentifiers::MAXIMUM_QOS);
+                buf.put_u8(*v);
+            }
+            Property::RetainAvailable(v) => {
+                buf.put_u8(PropertyIdentifiers::RETAIN_AVAILABLE);
+                buf.put_u8(*v);
+            }
+            Property::UserProperty(v) => {
+                buf.put_u8(PropertyIdentifiers::USER_PROPERTY);
+                buf.extend_from_slice(&encode_utf_string_pair(v.clone())?);
+            }
+            Property::MaximumPacketSize(v) => {
+                buf.put_u8(PropertyIdentifiers::MAXIMUM_PACKET_SIZE);
+                buf.put_u32(*v);
+            }
+            Property::WildcardSubscriptionAvailable(v) => {
+                buf.put_u8(PropertyIdentifiers::WILDCARD_SUBSCRIPTION_AVAILABLE);
+                buf.put_u8(*v);
+            }
+            Property::SubscriptionIdentifierAvailable(v) => {
+                buf.put_u8(PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER_AVAILABLE);
+                buf.put_u8(*v);
+            }
+            Property::SharedSubscriptionAvailable(v) => {
+                buf.put_u8(PropertyIdentifiers::SHARED_SUBSCRIPTION_AVAILABLE);
+                buf.put_u8(*v);
+            }
+        }
+    }
+    Ok(buf.to_bytes())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Properties {
     pub payload_format_indicator: Option<u8>,
     pub message_expiry_interval: Option<u32>,
     pub content_type: Option<String>,
     pub response_topic: Option<String>,
-    pub correlation_data: Option<String>,
-    pub subscription_identifier: Option<u32>,
+    pub correlation_data: Option<Bytes>,
+    /// Every Subscription Identifier seen, in wire order. The spec allows a forwarded PUBLISH
+    /// to carry more than one (one per matching subscription), so unlike most other fields here
+    /// this is never collapsed down to a single value.
+    pub subscription_identifier: Vec<u32>,
     pub session_expiry_interval: Option<u32>,
     pub assigned_client_identifier: Option<String>,
     pub server_keep_alive: Option<u16>,
     pub authentication_method: Option<String>,
-    pub authentication_data: Option<String>,
+    pub authentication_data: Option<Bytes>,
     pub request_problem_info: Option<u8>,
     pub will_delay_interval: Option<u32>,
     pub request_response_info: Option<u8>,
@@ -60,209 +374,466 @@ pub struct Properties {
     pub topic_alias: Option<u16>,
     pub maximum_qos: Option<u8>,
     pub retain_available: Option<u8>,
-    pub user_property: Option<Utf8Pair>,
+    /// Every User Property pair seen, in wire order. The spec allows repeats, so unlike the
+    /// other fields here this is never collapsed down to a single value.
+    pub user_properties: Vec<Utf8Pair>,
     pub maximum_packet_size: Option<u32>,
     pub wildcard_subscription_available: Option<u8>,
     pub subscription_identifier_available: Option<u8>,
     pub shared_subscription_available: Option<u8>,
 }
 
-pub fn extract_properties(stream: &mut Bytes) -> Result<Option<Properties>, Error> {
-    let (_prop_length, _read) = decode_variable_byte(stream);
-    let mut prop_length = _prop_length?;
-    if prop_length > 0 {
-        let mut payload_format_indicator: Option<u8> = None;
-        let mut message_expiry_interval: Option<u32> = None;
-        let mut content_type: Option<String> = None;
-        let mut response_topic: Option<String> = None;
-        let mut correlation_data: Option<String> = None; // binary data
-        let mut subscription_identifier: Option<u32> = None;
-        let mut session_expiry_interval: Option<u32> = None;
-        let mut assigned_client_identifier: Option<String> = None;
-        let mut server_keep_alive: Option<u16> = None;
-        let mut authentication_method: Option<String> = None;
-        let mut authentication_data: Option<String> = None; // binary data
-        let mut request_problem_info: Option<u8> = None;
-        let mut will_delay_interval: Option<u32> = None;
-        let mut request_response_info: Option<u8> = None;
-        let mut response_info: Option<String> = None;
-        let mut server_info: Option<String> = None;
-        let mut reason_string: Option<String> = None;
-        let mut receive_maximum: Option<u16> = None;
-        let mut topic_alias_maximum: Option<u16> = None;
-        let mut topic_alias: Option<u16> = None;
-        let mut maximum_qos: Option<u8> = None;
-        let mut retain_available: Option<u8> = None;
-        let mut user_property: Option<Utf8Pair> = None;
-        let mut maximum_packet_size: Option<u32> = None;
-        let mut wildcard_subscription_available: Option<u8> = None;
-        let mut subscription_identifier_available: Option<u8> = None;
-        let mut shared_subscription_available: Option<u8> = None;
-
-        {
-            while prop_length > 0 {
-                // initial 1 byte identifier for the property
-                let ident = stream.get_u8();
-                prop_length -= ByteLengths::BYTE_INT;
+/// Identifies which property block a [`Properties`] is being decoded for, so that
+/// [`extract_properties`] can reject identifiers the spec doesn't permit there. `Will` is the
+/// Will Properties block embedded in a CONNECT payload - it has its own allowed set, distinct
+/// from CONNECT's own property block, so it isn't just `PacketType::Connect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PropertyOwner {
+    Connect,
+    Will,
+    ConnAck,
+    Publish,
+    PubAck,
+    PubRec,
+    PubRel,
+    PubComp,
+    Subscribe,
+    SubAck,
+    Unsubscribe,
+    UnsubAck,
+    Disconnect,
+    Auth,
+}
 
-                // match identifier to extract properties
-                match ident {
-                    PropertyIdentifiers::PAYLOAD_FORMAT_INDICATOR => {
-                        payload_format_indicator = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::MESSAGE_EXPIRY_INTERVAL => {
-                        message_expiry_interval = Some(stream.get_u32());
-                        prop_length -= ByteLengths::FOUR_BYTE_INT;
-                    }
-                    PropertyIdentifiers::CONTENT_TYPE => {
-                        let (data, len) = decode_utf_string(stream);
-                        content_type = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::RESPONSE_TOPIC => {
-                        let (data, len) = decode_utf_string(stream);
-                        response_topic = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::CORRELATION_DATA => {
-                        let (data, len) = decode_utf_string(stream);
-                        correlation_data = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER => {
-                        subscription_identifier = Some(stream.get_u32());
-                        prop_length -= ByteLengths::FOUR_BYTE_INT;
-                    }
-                    PropertyIdentifiers::SESSION_EXPIRY_INTERVAL => {
-                        session_expiry_interval = Some(stream.get_u32());
-                        prop_length -= ByteLengths::FOUR_BYTE_INT;
-                    }
-                    PropertyIdentifiers::ASSIGNED_CLIENT_IDENTIFIER => {
-                        let (data, len) = decode_utf_string(stream);
-                        assigned_client_identifier = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::SERVER_KEEP_ALIVE => {
-                        server_keep_alive = Some(stream.get_u16());
-                        prop_length -= ByteLengths::FOUR_BYTE_INT;
-                    }
-                    PropertyIdentifiers::AUTHENTICATION_METHOD => {
-                        let (data, len) = decode_utf_string(stream);
-                        authentication_method = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::AUTHENTICATION_DATA => {
-                        let (data, len) = decode_utf_string(stream);
-                        authentication_data = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::REQUEST_PROBLEM_INFORMATION => {
-                        request_problem_info = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::WILL_DELAY_INTERVAL => {
-                        will_delay_interval = Some(stream.get_u32());
-                        prop_length -= ByteLengths::FOUR_BYTE_INT;
-                    }
-                    PropertyIdentifiers::REQUEST_RESPONSE_INFORMATION => {
-                        request_response_info = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::RESPONSE_INFO => {
-                        let (data, len) = decode_utf_string(stream);
-                        response_info = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::SERVER_INFO => {
-                        let (data, len) = decode_utf_string(stream);
-                        server_info = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::REASON_STRING => {
-                        let (data, len) = decode_utf_string(stream);
-                        reason_string = Some(data?);
-                        prop_length -= len;
-                    }
-                    PropertyIdentifiers::RECEIVE_MAXIMUM => {
-                        receive_maximum = Some(stream.get_u16());
-                        prop_length -= ByteLengths::TWO_BYTE_INT;
-                    }
-                    PropertyIdentifiers::TOPIC_ALIAS_MAXIMUM => {
-                        topic_alias_maximum = Some(stream.get_u16());
-                        prop_length -= ByteLengths::TWO_BYTE_INT;
-                    }
-                    PropertyIdentifiers::TOPIC_ALIAS => {
-                        topic_alias = Some(stream.get_u16());
-                        prop_length -= ByteLengths::TWO_BYTE_INT;
-                    }
-                    PropertyIdentifiers::MAXIMUM_QOS => {
-                        maximum_qos = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::RETAIN_AVAILABLE => {
-                        retain_available = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::USER_PROPERTY => {
-                        let (data, len) = decode_utf_string_pair(stream);
-                        user_property = Some(data?);
-                        prop_length -= len
-                    }
-                    PropertyIdentifiers::MAXIMUM_PACKET_SIZE => {
-                        maximum_packet_size = Some(stream.get_u32());
-                        prop_length -= ByteLengths::FOUR_BYTE_INT;
-                    }
-                    PropertyIdentifiers::WILDCARD_SUBSCRIPTION_AVAILABLE => {
-                        wildcard_subscription_available = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER_AVAILABLE => {
-                        subscription_identifier_available = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    PropertyIdentifiers::SHARED_SUBSCRIPTION_AVAILABLE => {
-                        shared_subscription_available = Some(stream.get_u8());
-                        prop_length -= ByteLengths::BYTE_INT;
-                    }
-                    _ => {
-                        return Err(Error::InvalidProperty);
-                    }
-                }
-            }
+/// The wire identifier of a decoded property, for matching against [`allowed_properties`].
+fn property_identifier(property: &Property) -> u8 {
+    match property {
+        Property::PayloadFormatIndicator(_) => PropertyIdentifiers::PAYLOAD_FORMAT_INDICATOR,
+        Property::MessageExpiryInterval(_) => PropertyIdentifiers::MESSAGE_EXPIRY_INTERVAL,
+        Property::ContentType(_) => PropertyIdentifiers::CONTENT_TYPE,
+        Property::ResponseTopic(_) => PropertyIdentifiers::RESPONSE_TOPIC,
+        Property::CorrelationData(_) => PropertyIdentifiers::CORRELATION_DATA,
+        Property::SubscriptionIdentifier(_) => PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER,
+        Property::SessionExpiryInterval(_) => PropertyIdentifiers::SESSION_EXPIRY_INTERVAL,
+        Property::AssignedClientIdentifier(_) => PropertyIdentifiers::ASSIGNED_CLIENT_IDENTIFIER,
+        Property::ServerKeepAlive(_) => PropertyIdentifiers::SERVER_KEEP_ALIVE,
+        Property::AuthenticationMethod(_) => PropertyIdentifiers::AUTHENTICATION_METHOD,
+        Property::AuthenticationData(_) => PropertyIdentifiers::AUTHENTICATION_DATA,
+        Property::RequestProblemInformation(_) => PropertyIdentifiers::REQUEST_PROBLEM_INFORMATION,
+        Property::WillDelayInterval(_) => PropertyIdentifiers::WILL_DELAY_INTERVAL,
+        Property::RequestResponseInformation(_) => PropertyIdentifiers::REQUEST_RESPONSE_INFORMATION,
+        Property::ResponseInformation(_) => PropertyIdentifiers::RESPONSE_INFO,
+        Property::ServerInformation(_) => PropertyIdentifiers::SERVER_INFO,
+        Property::ReasonString(_) => PropertyIdentifiers::REASON_STRING,
+        Property::ReceiveMaximum(_) => PropertyIdentifiers::RECEIVE_MAXIMUM,
+        Property::TopicAliasMaximum(_) => PropertyIdentifiers::TOPIC_ALIAS_MAXIMUM,
+        Property::TopicAlias(_) => PropertyIdentifiers::TOPIC_ALIAS,
+        Property::MaximumQoS(_) => PropertyIdentifiers::MAXIMUM_QOS,
+        Property::RetainAvailable(_) => PropertyIdentifiers::RETAIN_AVAILABLE,
+        Property::UserProperty(_) => PropertyIdentifiers::USER_PROPERTY,
+        Property::MaximumPacketSize(_) => PropertyIdentifiers::MAXIMUM_PACKET_SIZE,
+        Property::WildcardSubscriptionAvailable(_) => PropertyIdentifiers::WILDCARD_SUBSCRIPTION_AVAILABLE,
+        Property::SubscriptionIdentifierAvailable(_) => PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER_AVAILABLE,
+        Property::SharedSubscriptionAvailable(_) => PropertyIdentifiers::SHARED_SUBSCRIPTION_AVAILABLE,
+    }
+}
+
+/// The property identifiers a packet type's property block is allowed to carry, per the
+/// per-packet tables in the MQTT v5.0 spec (sections 3.1.2.11, 3.2.2.3, 3.3.2.3, 3.8.2.1, ...).
+fn allowed_properties(owner: PropertyOwner) -> &'static [u8] {
+    use PropertyIdentifiers as P;
+    match owner {
+        PropertyOwner::Connect => &[
+            P::SESSION_EXPIRY_INTERVAL, P::RECEIVE_MAXIMUM, P::MAXIMUM_PACKET_SIZE,
+            P::TOPIC_ALIAS_MAXIMUM, P::REQUEST_RESPONSE_INFORMATION, P::REQUEST_PROBLEM_INFORMATION,
+            P::USER_PROPERTY, P::AUTHENTICATION_METHOD, P::AUTHENTICATION_DATA,
+        ],
+        PropertyOwner::Will => &[
+            P::WILL_DELAY_INTERVAL, P::PAYLOAD_FORMAT_INDICATOR, P::MESSAGE_EXPIRY_INTERVAL,
+            P::CONTENT_TYPE, P::RESPONSE_TOPIC, P::CORRELATION_DATA, P::USER_PROPERTY,
+        ],
+        PropertyOwner::ConnAck => &[
+            P::SESSION_EXPIRY_INTERVAL, P::RECEIVE_MAXIMUM, P::MAXIMUM_QOS, P::RETAIN_AVAILABLE,
+            P::MAXIMUM_PACKET_SIZE, P::ASSIGNED_CLIENT_IDENTIFIER, P::TOPIC_ALIAS_MAXIMUM,
+            P::REASON_STRING, P::USER_PROPERTY, P::WILDCARD_SUBSCRIPTION_AVAILABLE,
+            P::SUBSCRIPTION_IDENTIFIER_AVAILABLE, P::SHARED_SUBSCRIPTION_AVAILABLE,
+            P::SERVER_KEEP_ALIVE, P::RESPONSE_INFO, P::SERVER_INFO, P::AUTHENTICATION_METHOD,
+            P::AUTHENTICATION_DATA,
+        ],
+        PropertyOwner::Publish => &[
+            P::PAYLOAD_FORMAT_INDICATOR, P::MESSAGE_EXPIRY_INTERVAL, P::CONTENT_TYPE,
+            P::RESPONSE_TOPIC, P::CORRELATION_DATA, P::SUBSCRIPTION_IDENTIFIER, P::TOPIC_ALIAS,
+            P::USER_PROPERTY,
+        ],
+        PropertyOwner::PubAck | PropertyOwner::PubRec | PropertyOwner::PubRel | PropertyOwner::PubComp => &[
+            P::REASON_STRING, P::USER_PROPERTY,
+        ],
+        PropertyOwner::Subscribe => &[P::SUBSCRIPTION_IDENTIFIER, P::USER_PROPERTY],
+        PropertyOwner::SubAck | PropertyOwner::UnsubAck => &[P::REASON_STRING, P::USER_PROPERTY],
+        PropertyOwner::Unsubscribe => &[P::USER_PROPERTY],
+        PropertyOwner::Disconnect => &[
+            P::SESSION_EXPIRY_INTERVAL, P::REASON_STRING, P::USER_PROPERTY, P::SERVER_INFO,
+        ],
+        PropertyOwner::Auth => &[
+            P::AUTHENTICATION_METHOD, P::AUTHENTICATION_DATA, P::REASON_STRING, P::USER_PROPERTY,
+        ],
+    }
+}
+
+pub fn extract_properties(stream: &mut Bytes, owner: PropertyOwner) -> Result<Option<Properties>, Error> {
+    let properties = match decode_properties(stream)? {
+        Some(properties) => properties,
+        None => return Ok(None),
+    };
+
+    let allowed = allowed_properties(owner);
+    for property in &properties {
+        if !allowed.contains(&property_identifier(property)) {
+            return Err(Error::InvalidPropertyForPacket);
         }
+    }
+
+    let mut payload_format_indicator: Option<u8> = None;
+    let mut message_expiry_interval: Option<u32> = None;
+    let mut content_type: Option<String> = None;
+    let mut response_topic: Option<String> = None;
+    let mut correlation_data: Option<Bytes> = None;
+    let mut subscription_identifier: Vec<u32> = Vec::new();
+    let mut session_expiry_interval: Option<u32> = None;
+    let mut assigned_client_identifier: Option<String> = None;
+    let mut server_keep_alive: Option<u16> = None;
+    let mut authentication_method: Option<String> = None;
+    let mut authentication_data: Option<Bytes> = None;
+    let mut request_problem_info: Option<u8> = None;
+    let mut will_delay_interval: Option<u32> = None;
+    let mut request_response_info: Option<u8> = None;
+    let mut response_info: Option<String> = None;
+    let mut server_info: Option<String> = None;
+    let mut reason_string: Option<String> = None;
+    let mut receive_maximum: Option<u16> = None;
+    let mut topic_alias_maximum: Option<u16> = None;
+    let mut topic_alias: Option<u16> = None;
+    let mut maximum_qos: Option<u8> = None;
+    let mut retain_available: Option<u8> = None;
+    let mut user_properties: Vec<Utf8Pair> = Vec::new();
+    let mut maximum_packet_size: Option<u32> = None;
+    let mut wildcard_subscription_available: Option<u8> = None;
+    let mut subscription_identifier_available: Option<u8> = None;
+    let mut shared_subscription_available: Option<u8> = None;
+
+    for property in properties {
+        match property {
+            Property::PayloadFormatIndicator(v) => payload_format_indicator = Some(v),
+            Property::MessageExpiryInterval(v) => message_expiry_interval = Some(v),
+            Property::ContentType(v) => content_type = Some(v),
+            Property::ResponseTopic(v) => response_topic = Some(v),
+            Property::CorrelationData(v) => correlation_data = Some(v),
+            Property::SubscriptionIdentifier(v) => subscription_identifier.push(v),
+            Property::SessionExpiryInterval(v) => session_expiry_interval = Some(v),
+            Property::AssignedClientIdentifier(v) => assigned_client_identifier = Some(v),
+            Property::ServerKeepAlive(v) => server_keep_alive = Some(v),
+            Property::AuthenticationMethod(v) => authentication_method = Some(v),
+            Property::AuthenticationData(v) => authentication_data = Some(v),
+            Property::RequestProblemInformation(v) => request_problem_info = Some(v),
+            Property::WillDelayInterval(v) => will_delay_interval = Some(v),
+            Property::RequestResponseInformation(v) => request_response_info = Some(v),
+            Property::ResponseInformation(v) => response_info = Some(v),
+            Property::ServerInformation(v) => server_info = Some(v),
+            Property::ReasonString(v) => reason_string = Some(v),
+            Property::ReceiveMaximum(v) => receive_maximum = Some(v),
+            Property::TopicAliasMaximum(v) => topic_alias_maximum = Some(v),
+            Property::TopicAlias(v) => topic_alias = Some(v),
+            Property::MaximumQoS(v) => maximum_qos = Some(v),
+            Property::RetainAvailable(v) => retain_available = Some(v),
+            Property::UserProperty(v) => user_properties.push(v),
+            Property::MaximumPacketSize(v) => maximum_packet_size = Some(v),
+            Property::WildcardSubscriptionAvailable(v) => wildcard_subscription_available = Some(v),
+            Property::SubscriptionIdentifierAvailable(v) => subscription_identifier_available = Some(v),
+            Property::SharedSubscriptionAvailable(v) => shared_subscription_available = Some(v),
+        }
+    }
 
-        let props = Properties {
-            payload_format_indicator,
-            message_expiry_interval,
-            content_type,
-            response_topic,
-            correlation_data,
-            subscription_identifier,
-            session_expiry_interval,
-            assigned_client_identifier,
-            server_keep_alive,
-            authentication_method,
-            authentication_data,
-            request_problem_info,
-            will_delay_interval,
-            request_response_info,
-            response_info,
-            server_info,
-            reason_string,
-            receive_maximum,
-            topic_alias_maximum,
-            topic_alias,
-            maximum_qos,
-            retain_available,
-            user_property,
-            maximum_packet_size,
-            wildcard_subscription_available,
-            subscription_identifier_available,
-            shared_subscription_available,
+    Ok(Some(Properties {
+        payload_format_indicator,
+        message_expiry_interval,
+        content_type,
+        response_topic,
+        correlation_data,
+        subscription_identifier,
+        session_expiry_interval,
+        assigned_client_identifier,
+        server_keep_alive,
+        authentication_method,
+        authentication_data,
+        request_problem_info,
+        will_delay_interval,
+        request_response_info,
+        response_info,
+        server_info,
+        reason_string,
+        receive_maximum,
+        topic_alias_maximum,
+        topic_alias,
+        maximum_qos,
+        retain_available,
+        user_properties,
+        maximum_packet_size,
+        wildcard_subscription_available,
+        subscription_identifier_available,
+        shared_subscription_available,
+    }))
+}
+
+impl Properties {
+    /// Flattens the present fields back into the ordered, duplicate-respecting list
+    /// `encode_properties` expects, in ascending identifier order. The inverse of the
+    /// collapsing loop in `extract_properties`.
+    fn as_property_list(&self) -> Vec<Property> {
+        let mut properties = Vec::new();
+        if let Some(v) = self.payload_format_indicator {
+            properties.push(Property::PayloadFormatIndicator(v));
+        }
+        if let Some(v) = self.message_expiry_interval {
+            properties.push(Property::MessageExpiryInterval(v));
+        }
+        if let Some(v) = &self.content_type {
+            properties.push(Property::ContentType(v.clone()));
+        }
+        if let Some(v) = &self.response_topic {
+            properties.push(Property::ResponseTopic(v.clone()));
+        }
+        if let Some(v) = &self.correlation_data {
+            properties.push(Property::CorrelationData(v.clone()));
+        }
+        for subscription_identifier in &self.subscription_identifier {
+            properties.push(Property::SubscriptionIdentifier(*subscription_identifier));
+        }
+        if let Some(v) = self.session_expiry_interval {
+            properties.push(Property::SessionExpiryInterval(v));
+        }
+        if let Some(v) = &self.assigned_client_identifier {
+            properties.push(Property::AssignedClientIdentifier(v.clone()));
+        }
+        if let Some(v) = self.server_keep_alive {
+            properties.push(Property::ServerKeepAlive(v));
+        }
+        if let Some(v) = &self.authentication_method {
+            properties.push(Property::AuthenticationMethod(v.clone()));
+        }
+        if let Some(v) = &self.authentication_data {
+            properties.push(Property::AuthenticationData(v.clone()));
+        }
+        if let Some(v) = self.request_problem_info {
+            properties.push(Property::RequestProblemInformation(v));
+        }
+        if let Some(v) = self.will_delay_interval {
+            properties.push(Property::WillDelayInterval(v));
+        }
+        if let Some(v) = self.request_response_info {
+            properties.push(Property::RequestResponseInformation(v));
+        }
+        if let Some(v) = &self.response_info {
+            properties.push(Property::ResponseInformation(v.clone()));
+        }
+        if let Some(v) = &self.server_info {
+            properties.push(Property::ServerInformation(v.clone()));
+        }
+        if let Some(v) = &self.reason_string {
+            properties.push(Property::ReasonString(v.clone()));
+        }
+        if let Some(v) = self.receive_maximum {
+            properties.push(Property::ReceiveMaximum(v));
+        }
+        if let Some(v) = self.topic_alias_maximum {
+            properties.push(Property::TopicAliasMaximum(v));
+        }
+        if let Some(v) = self.topic_alias {
+            properties.push(Property::TopicAlias(v));
+        }
+        if let Some(v) = self.maximum_qos {
+            properties.push(Property::MaximumQoS(v));
+        }
+        if let Some(v) = self.retain_available {
+            properties.push(Property::RetainAvailable(v));
+        }
+        for user_property in &self.user_properties {
+            properties.push(Property::UserProperty(user_property.clone()));
+        }
+        if let Some(v) = self.maximum_packet_size {
+            properties.push(Property::MaximumPacketSize(v));
+        }
+        if let Some(v) = self.wildcard_subscription_available {
+            properties.push(Property::WildcardSubscriptionAvailable(v));
+        }
+        if let Some(v) = self.subscription_identifier_available {
+            properties.push(Property::SubscriptionIdentifierAvailable(v));
+        }
+        if let Some(v) = self.shared_subscription_available {
+            properties.push(Property::SharedSubscriptionAvailable(v));
+        }
+        properties
+    }
+
+    /// Size of this property block's identifier+value bytes, not including the leading
+    /// variable-byte length prefix. Following the `EncodeLtd`/`encoded_size` split used by
+    /// ntex-mqtt, callers compute this first so they can write a correctly-sized variable-byte
+    /// length ahead of the properties themselves.
+    pub fn encoded_len(&self) -> Result<usize, Error> {
+        Ok(encode_properties(&self.as_property_list())?.len())
+    }
+
+    /// Encodes this property block's identifier+value bytes. Does not prepend the variable-byte
+    /// length prefix - callers add that themselves using [`Properties::encoded_len`], matching
+    /// the convention used by every `*Properties::disassemble`.
+    pub fn disassemble(&self) -> Result<Bytes, Error> {
+        encode_properties(&self.as_property_list())
+    }
+}
+
+#[cfg(test)]
+mod test_properties {
+    use super::*;
+    use alloc::borrow::ToOwned;
+    use alloc::vec;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn decode_properties_errors_instead_of_panicking_on_a_truncated_block() {
+        // length prefix claims 4 bytes of properties, but only the identifier byte is present
+        let mut stream = Bytes::from(&[0x04, PropertyIdentifiers::SESSION_EXPIRY_INTERVAL][..]);
+        match decode_properties(&mut stream) {
+            Err(Error::UnexpectedEof) => {}
+            other => panic!("Expected UnexpectedEof, got = {:?}", other),
+        }
+    }
+
+    #[test]
+    fn subscription_identifier_is_a_variable_byte_integer_and_can_repeat() {
+        let properties = vec![
+            Property::SubscriptionIdentifier(1),
+            Property::SubscriptionIdentifier(268_435_455), // max variable byte integer value
+        ];
+
+        let encoded = encode_properties(&properties).unwrap();
+        // 1-byte identifier + 1-byte value, then 1-byte identifier + 4-byte value
+        assert_eq!(encoded.len(), 2 + 5);
+
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&crate::encode_variable_byte(encoded.len() as i32).unwrap());
+        stream.extend_from_slice(&encoded);
+        let mut stream = stream.to_bytes();
+
+        let decoded = decode_properties(&mut stream).unwrap().unwrap();
+        assert_eq!(decoded, properties);
+    }
+
+    #[test]
+    fn subscription_identifier_of_zero_is_rejected() {
+        let properties = vec![Property::SubscriptionIdentifier(0)];
+        let encoded = encode_properties(&properties).unwrap();
+
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&crate::encode_variable_byte(encoded.len() as i32).unwrap());
+        stream.extend_from_slice(&encoded);
+        let mut stream = stream.to_bytes();
+
+        match decode_properties(&mut stream) {
+            Err(Error::InvalidProperty) => {}
+            other => panic!("Expected InvalidProperty, got = {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_user_properties_round_trip_in_order() {
+        let properties = vec![
+            Property::ReasonString("because".to_owned()),
+            Property::UserProperty(("k1".to_owned(), "v1".to_owned())),
+            Property::UserProperty(("k2".to_owned(), "v2".to_owned())),
+        ];
+
+        let encoded = encode_properties(&properties).unwrap();
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&crate::encode_variable_byte(encoded.len() as i32).unwrap());
+        stream.extend_from_slice(&encoded);
+        let mut stream = stream.to_bytes();
+
+        let decoded = decode_properties(&mut stream).unwrap().unwrap();
+        assert_eq!(decoded, properties);
+    }
+
+    #[test]
+    fn properties_struct_round_trips_through_disassemble_and_extract() {
+        let properties = Properties {
+            payload_format_indicator: None,
+            message_expiry_interval: Some(60),
+            content_type: Some("application/json".to_owned()),
+            response_topic: Some("reply/topic".to_owned()),
+            correlation_data: Some(Bytes::from(&[0xDE, 0xAD, 0xBE, 0xEF][..])),
+            subscription_identifier: vec![11, 22],
+            session_expiry_interval: None,
+            assigned_client_identifier: None,
+            server_keep_alive: None,
+            authentication_method: None,
+            authentication_data: None,
+            request_problem_info: None,
+            will_delay_interval: None,
+            request_response_info: None,
+            response_info: None,
+            server_info: None,
+            reason_string: None,
+            receive_maximum: None,
+            topic_alias_maximum: None,
+            topic_alias: None,
+            maximum_qos: None,
+            retain_available: None,
+            user_properties: vec![("k1".to_owned(), "v1".to_owned()), ("k2".to_owned(), "v2".to_owned())],
+            maximum_packet_size: None,
+            wildcard_subscription_available: None,
+            subscription_identifier_available: None,
+            shared_subscription_available: None,
         };
 
-        return Ok(Some(props));
+        let encoded = properties.disassemble().unwrap();
+        assert_eq!(encoded.len(), properties.encoded_len().unwrap());
+
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&crate::encode_variable_byte(encoded.len() as i32).unwrap());
+        stream.extend_from_slice(&encoded);
+        let mut stream = stream.to_bytes();
+
+        let decoded = extract_properties(&mut stream, PropertyOwner::Publish).unwrap().unwrap();
+        assert_eq!(decoded, properties);
+    }
+
+    #[test]
+    fn server_keep_alive_property_is_rejected_on_a_publish() {
+        let properties = vec![Property::ServerKeepAlive(30)];
+        let encoded = encode_properties(&properties).unwrap();
+
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&crate::encode_variable_byte(encoded.len() as i32).unwrap());
+        stream.extend_from_slice(&encoded);
+        let mut stream = stream.to_bytes();
+
+        match extract_properties(&mut stream, PropertyOwner::Publish) {
+            Err(Error::InvalidPropertyForPacket) => {}
+            other => panic!("Expected InvalidPropertyForPacket, got = {:?}", other),
+        }
+    }
+
+    #[test]
+    fn topic_alias_property_is_rejected_on_a_subscribe() {
+        let properties = vec![Property::TopicAlias(7)];
+        let encoded = encode_properties(&properties).unwrap();
+
+        let mut stream = BytesMut::new();
+        stream.extend_from_slice(&crate::encode_variable_byte(encoded.len() as i32).unwrap());
+        stream.extend_from_slice(&encoded);
+        let mut stream = stream.to_bytes();
+
+        match extract_properties(&mut stream, PropertyOwner::Subscribe) {
+            Err(Error::InvalidPropertyForPacket) => {}
+            other => panic!("Expected InvalidPropertyForPacket, got = {:?}", other),
+        }
     }
-    return Ok(None);
-}
\ No newline at end of file
+}
@@ -1,24 +1,45 @@
-use crate::{Error, Utf8Pair};
+use crate::{encode_utf_string, encode_utf_string_pair, encode_variable_byte, puback_reason_code, Error, PubAckReason, Utf8Pair};
 use crate::FixedHeader;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use alloc::string::String;
-use crate::control::properties::extract_properties;
+use alloc::vec::Vec;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct PubAckProperties {
     pub reason_string: Option<String>,
-    pub user_property: Option<Utf8Pair>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
+impl PubAckProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct PubAck {
     pub pkid: u16,
+    pub reason_code: PubAckReason,
     pub properties: Option<PubAckProperties>
 }
 
 impl PubAck {
     pub(crate) fn assemble(fixed_header: FixedHeader, mut bytes: Bytes) -> Result<Self, Error> {
-        if fixed_header.remaining_len != 2 {
+        if fixed_header.remaining_len < 2 {
             return Err(Error::PayloadSizeIncorrect);
         }
 
@@ -26,18 +47,30 @@ impl PubAck {
         bytes.advance(variable_header_index);
         let pkid = bytes.get_u16();
 
-        let _props = extract_properties(&mut bytes)?;
+        // Reason Code and Properties are both omitted on the wire when the Reason Code would
+        // be Success and there are no Properties (MQTT-3.4.2.1).
+        let reason_code = if fixed_header.remaining_len > 2 {
+            puback_reason_code(bytes.get_u8())?
+        } else {
+            PubAckReason::Success
+        };
+
+        let _props = if fixed_header.remaining_len > 3 {
+            extract_properties(&mut bytes, PropertyOwner::PubAck)?
+        } else {
+            None
+        };
         let puback = match _props {
             Some(props)=>{
                 let properties = Some(
                     PubAckProperties{
                         reason_string: props.reason_string,
-                        user_property: props.user_property
+                        user_properties: props.user_properties
                     }
                 );
-                PubAck { pkid, properties }
+                PubAck { pkid, reason_code, properties }
             }
-            None => PubAck { pkid, properties: None }
+            None => PubAck { pkid, reason_code, properties: None }
         };
 
         Ok(puback)
@@ -45,8 +78,28 @@ impl PubAck {
 }
 
 impl PubAck {
-    pub fn new(pkid: u16, properties: Option<PubAckProperties>) -> PubAck {
-        PubAck { pkid, properties }
+    pub fn new(pkid: u16, reason_code: PubAckReason, properties: Option<PubAckProperties>) -> PubAck {
+        PubAck { pkid, reason_code, properties }
+    }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+        var_header.put_u8(self.reason_code as u8);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b0100_0000); // PUBACK, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte(var_header.len() as i32)?);
+        packet.extend_from_slice(&var_header);
+
+        Ok(packet.to_bytes())
     }
 }
 
@@ -55,7 +108,7 @@ mod test_publish {
     use crate::*;
     use alloc::borrow::ToOwned;
     use alloc::vec;
-    use bytes::{Bytes};
+    use bytes::{Bytes, BytesMut};
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -78,6 +131,20 @@ mod test_publish {
             packet => panic!("Invalid packet = {:?}", packet),
         };
 
-        assert_eq!(packet, PubAck { pkid: 10, properties: None });
+        assert_eq!(packet, PubAck { pkid: 10, reason_code: PubAckReason::Success, properties: None });
+    }
+
+    #[test]
+    fn puback_write_and_read_round_trips() {
+        let puback = PubAck::new(10, PubAckReason::NoMatchingSubscribers, None);
+        let mut stream = BytesMut::from(mqtt_write(Packet::PubAck(puback.clone())).unwrap().as_ref());
+
+        let packet = mqtt_read(&mut stream, 1024).unwrap();
+        let packet = match packet {
+            Packet::PubAck(packet) => packet,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(packet, puback);
     }
 }
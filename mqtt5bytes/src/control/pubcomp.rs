@@ -1,13 +1,29 @@
-use crate::Error;
+use crate::{encode_utf_string, encode_utf_string_pair, encode_variable_byte, Error, Utf8Pair};
 use crate::FixedHeader;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use alloc::string::String;
-use crate::control::properties::extract_properties;
+use alloc::vec::Vec;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PubCompProperties {
     pub reason_string: Option<String>,
-    pub user_property: Option<String>,
+    pub user_properties: Vec<Utf8Pair>,
+}
+
+impl PubCompProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,20 +34,20 @@ pub struct PubComp {
 
 impl PubComp {
     pub(crate) fn assemble(fixed_header: FixedHeader, mut bytes: Bytes) -> Result<Self, Error> {
-        if fixed_header.remaining_len != 2 {
+        if fixed_header.remaining_len < 2 {
             return Err(Error::PayloadSizeIncorrect);
         }
 
         let variable_header_index = fixed_header.header_len;
         bytes.advance(variable_header_index);
         let pkid = bytes.get_u16();
-        let _props = extract_properties(&mut bytes)?;
+        let _props = extract_properties(&mut bytes, PropertyOwner::PubComp)?;
         let pubcomp = match _props {
             Some(props) => {
                 let properties = Some(
                     PubCompProperties {
                         reason_string: props.reason_string,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                     }
                 );
                 PubComp { pkid, properties }
@@ -48,4 +64,23 @@ impl PubComp {
     pub fn new(pkid: u16, properties: Option<PubCompProperties>) -> PubComp {
         PubComp { pkid, properties }
     }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b0111_0000); // PUBCOMP, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte(var_header.len() as i32)?);
+        packet.extend_from_slice(&var_header);
+
+        Ok(packet.to_bytes())
+    }
 }
@@ -1,22 +1,67 @@
-use crate::{extract_mqtt_string, qos, Error, FixedHeader, QoS, Utf8Pair};
+use crate::{encode_binary_data, encode_utf_string, encode_utf_string_pair, encode_variable_byte, extract_mqtt_string, qos, Error, FixedHeader, QoS, Utf8Pair};
 use alloc::string::String;
 use alloc::vec::Vec;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use core::fmt;
-use crate::control::properties::extract_properties;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct PublishProperties {
     pub payload_format_indicator: Option<u8>,
     pub message_expiry_interval: Option<u32>,
     pub topic_alias: Option<u16>,
     pub response_topic: Option<String>,
-    pub correlation_data: Option<String>,
-    pub user_property: Option<Utf8Pair>,
-    pub subscription_identifier: Option<u32>,
+    pub correlation_data: Option<Bytes>,
+    pub user_properties: Vec<Utf8Pair>,
+    /// Every Subscription Identifier this PUBLISH was forwarded under, one per matching
+    /// subscription that set one - the spec allows more than one on a forwarded PUBLISH.
+    pub subscription_identifier: Vec<u32>,
     pub content_type: Option<String>,
 }
 
+impl PublishProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(payload_format_indicator) = self.payload_format_indicator {
+            props.put_u8(PropertyIdentifiers::PAYLOAD_FORMAT_INDICATOR);
+            props.put_u8(payload_format_indicator);
+        }
+        if let Some(message_expiry_interval) = self.message_expiry_interval {
+            props.put_u8(PropertyIdentifiers::MESSAGE_EXPIRY_INTERVAL);
+            props.put_u32(message_expiry_interval);
+        }
+        if let Some(topic_alias) = self.topic_alias {
+            props.put_u8(PropertyIdentifiers::TOPIC_ALIAS);
+            props.put_u16(topic_alias);
+        }
+        if let Some(response_topic) = &self.response_topic {
+            props.put_u8(PropertyIdentifiers::RESPONSE_TOPIC);
+            props.extend_from_slice(&encode_utf_string(response_topic.clone())?);
+        }
+        if let Some(correlation_data) = &self.correlation_data {
+            props.put_u8(PropertyIdentifiers::CORRELATION_DATA);
+            props.extend_from_slice(&encode_binary_data(correlation_data.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        for subscription_identifier in &self.subscription_identifier {
+            props.put_u8(PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER);
+            props.extend_from_slice(&encode_variable_byte(*subscription_identifier as i32)?);
+        }
+        if let Some(content_type) = &self.content_type {
+            props.put_u8(PropertyIdentifiers::CONTENT_TYPE);
+            props.extend_from_slice(&encode_utf_string(content_type.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq)]
 pub struct Publish {
     pub qos: QoS,
@@ -50,7 +95,7 @@ impl Publish {
             return Err(Error::PacketIdZero);
         }
 
-        let _props = extract_properties(&mut bytes)?;
+        let _props = extract_properties(&mut payload, PropertyOwner::Publish)?;
 
         let publish = match _props {
             Some(props) => {
@@ -61,7 +106,7 @@ impl Publish {
                         topic_alias: props.topic_alias,
                         response_topic: props.response_topic,
                         correlation_data: props.correlation_data,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                         subscription_identifier: props.subscription_identifier,
                         content_type: props.content_type,
                     }
@@ -113,6 +158,34 @@ impl Publish {
         self.pkid = pkid;
         self
     }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.extend_from_slice(&encode_utf_string(self.topic.clone())?);
+        if self.qos != QoS::AtMostOnce {
+            var_header.put_u16(self.pkid);
+        }
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let byte1 = 0b0011_0000
+            | ((self.dup as u8) << 3)
+            | ((self.qos as u8) << 1)
+            | (self.retain as u8);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(byte1);
+        packet.extend_from_slice(&encode_variable_byte((var_header.len() + self.payload.len()) as i32)?);
+        packet.extend_from_slice(&var_header);
+        packet.extend_from_slice(&self.payload);
+
+        Ok(packet.to_bytes())
+    }
 }
 
 impl fmt::Debug for Publish {
@@ -251,4 +324,75 @@ mod test_publish {
             }
         );
     }
+
+    #[test]
+    fn publish_write_and_read_round_trips() {
+        let mut packet = Publish::new("a/b", QoS::AtLeastOnce, vec![0xF1, 0xF2, 0xF3, 0xF4]);
+        packet.set_pkid(10);
+
+        let bytes = mqtt_write(Packet::Publish(packet.clone())).unwrap();
+        let mut stream = BytesMut::from(&bytes[..]);
+
+        let read_back = mqtt_read(&mut stream, 100).unwrap();
+        let read_back = match read_back {
+            Packet::Publish(read_back) => read_back,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(read_back.topic, packet.topic);
+        assert_eq!(read_back.pkid, packet.pkid);
+        assert_eq!(read_back.qos, packet.qos);
+        assert_eq!(read_back.payload, packet.payload);
+    }
+
+    #[test]
+    fn duplicate_user_properties_survive_a_full_publish_round_trip() {
+        let mut packet = Publish::new("a/b", QoS::AtLeastOnce, vec![0xF1, 0xF2]);
+        packet.set_pkid(10);
+        packet.properties = Some(PublishProperties {
+            payload_format_indicator: None,
+            message_expiry_interval: None,
+            topic_alias: None,
+            response_topic: None,
+            correlation_data: None,
+            user_properties: vec![
+                ("k1".to_owned(), "v1".to_owned()),
+                ("k1".to_owned(), "v2".to_owned()),
+            ],
+            subscription_identifier: vec![],
+            content_type: None,
+        });
+
+        let bytes = mqtt_write(Packet::Publish(packet.clone())).unwrap();
+        let mut stream = BytesMut::from(&bytes[..]);
+
+        let read_back = mqtt_read(&mut stream, 100).unwrap();
+        let read_back = match read_back {
+            Packet::Publish(read_back) => read_back,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(
+            read_back.properties.unwrap().user_properties,
+            vec![
+                ("k1".to_owned(), "v1".to_owned()),
+                ("k1".to_owned(), "v2".to_owned()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn publish_json_round_trips() {
+        let mut packet = Publish::new("a/b", QoS::AtLeastOnce, vec![0xF1, 0xF2, 0xF3, 0xF4]);
+        packet.set_pkid(10);
+
+        let json = serde_json::to_string(&packet).unwrap();
+        let read_back: Publish = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(read_back.topic, packet.topic);
+        assert_eq!(read_back.pkid, packet.pkid);
+        assert_eq!(read_back.qos, packet.qos);
+        assert_eq!(read_back.payload, packet.payload);
+    }
 }
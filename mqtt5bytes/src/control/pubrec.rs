@@ -1,6 +1,6 @@
-use crate::Error;
+use crate::{checked_get_u16, encode_variable_byte, Error};
 use crate::FixedHeader;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PubRec {
@@ -15,7 +15,7 @@ impl PubRec {
 
         let variable_header_index = fixed_header.header_len;
         bytes.advance(variable_header_index);
-        let pkid = bytes.get_u16();
+        let pkid = checked_get_u16(&mut bytes)?;
         let pubrec = PubRec { pkid };
 
         Ok(pubrec)
@@ -26,4 +26,16 @@ impl PubRec {
     pub fn new(pkid: u16) -> PubRec {
         PubRec { pkid }
     }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b0101_0000); // PUBREC, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte(var_header.len() as i32)?);
+        packet.extend_from_slice(&var_header);
+
+        Ok(packet.to_bytes())
+    }
 }
@@ -1,19 +1,38 @@
-use crate::{qos, Error, FixedHeader, SubscribeReturnCodes, Utf8Pair};
+use crate::{encode_utf_string, encode_utf_string_pair, encode_variable_byte, subscribe_reason_code, Error, FixedHeader, SubscribeReasonCode, Utf8Pair};
 use alloc::vec::Vec;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use alloc::string::String;
-use crate::control::properties::extract_properties;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct SubAckProperties {
     pub reason_string: Option<String>,
-    pub user_property: Option<Utf8Pair>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
+impl SubAckProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct SubAck {
     pub pkid: u16,
-    pub return_codes: Vec<SubscribeReturnCodes>,
+    pub return_codes: Vec<SubscribeReasonCode>,
     properties: Option<SubAckProperties>,
 }
 
@@ -24,25 +43,26 @@ impl SubAck {
 
         let pkid = bytes.get_u16();
         let mut payload_bytes = fixed_header.remaining_len - 2;
+
+        // MQTT 5 puts the property block in the variable header, ahead of the reason codes.
+        let props_start = bytes.len();
+        let _props = extract_properties(&mut bytes, PropertyOwner::SubAck)?;
+        payload_bytes -= props_start - bytes.len();
+
         let mut return_codes = Vec::with_capacity(payload_bytes);
 
         while payload_bytes > 0 {
             let return_code = bytes.get_u8();
-            if return_code >> 7 == 1 {
-                return_codes.push(SubscribeReturnCodes::Failure)
-            } else {
-                return_codes.push(SubscribeReturnCodes::Success(qos(return_code & 0x3)?));
-            }
+            return_codes.push(subscribe_reason_code(return_code)?);
             payload_bytes -= 1
         }
 
-        let _props = extract_properties(&mut bytes)?;
         let suback = match _props {
             Some(props) => {
                 let properties = Some(
                     SubAckProperties {
                         reason_string: props.reason_string,
-                        user_property: props.user_property,
+                        user_properties: props.user_properties,
                     }
                 );
                 SubAck { pkid, return_codes, properties }
@@ -55,9 +75,34 @@ impl SubAck {
 }
 
 impl SubAck {
-    pub fn new(pkid: u16, return_codes: Vec<SubscribeReturnCodes>, properties: Option<SubAckProperties>) -> SubAck {
+    pub fn new(pkid: u16, return_codes: Vec<SubscribeReasonCode>, properties: Option<SubAckProperties>) -> SubAck {
         SubAck { pkid, return_codes, properties }
     }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut payload = BytesMut::new();
+        for return_code in self.return_codes.iter() {
+            payload.put_u8(*return_code as u8);
+        }
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b1001_0000); // SUBACK, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte((var_header.len() + payload.len()) as i32)?);
+        packet.extend_from_slice(&var_header);
+        packet.extend_from_slice(&payload);
+
+        Ok(packet.to_bytes())
+    }
 }
 
 #[cfg(test)]
@@ -88,9 +133,29 @@ mod test_publish {
             packet,
             SubAck {
                 pkid: 15,
-                return_codes: vec![SubscribeReturnCodes::Success(QoS::AtLeastOnce), SubscribeReturnCodes::Failure],
+                return_codes: vec![SubscribeReasonCode::GrantedQoS1, SubscribeReasonCode::UnspecifiedError],
                 properties: None,
             }
         );
     }
+
+    #[test]
+    fn suback_write_and_read_round_trips() {
+        let packet = SubAck::new(
+            15,
+            vec![SubscribeReasonCode::GrantedQoS1, SubscribeReasonCode::UnspecifiedError],
+            None,
+        );
+
+        let bytes = mqtt_write(Packet::SubAck(packet.clone())).unwrap();
+        let mut stream = BytesMut::from(&bytes[..]);
+
+        let read_back = mqtt_read(&mut stream, 100).unwrap();
+        let read_back = match read_back {
+            Packet::SubAck(packet) => packet,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(read_back, packet);
+    }
 }
@@ -1,17 +1,36 @@
-use crate::{extract_mqtt_string, qos, Error, FixedHeader, QoS, SubscribeTopic};
+use crate::{encode_utf_string, encode_utf_string_pair, encode_variable_byte, extract_mqtt_string, qos, retain_handling, Error, FixedHeader, QoS, RetainHandling, SubscribeTopic, Utf8Pair};
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use core::fmt;
-use crate::control::properties::extract_properties;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct SubscribeProperties {
     pub subscription_identifier: Option<u32>,
-    pub user_property: Option<String>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
+impl SubscribeProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(subscription_identifier) = self.subscription_identifier {
+            props.put_u8(PropertyIdentifiers::SUBSCRIPTION_IDENTIFIER);
+            props.extend_from_slice(&encode_variable_byte(subscription_identifier as i32)?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq)]
 pub struct Subscribe {
     pub pkid: u16,
@@ -29,22 +48,38 @@ impl Subscribe {
         let mut payload_bytes = fixed_header.remaining_len - 2;
         let mut topics = Vec::new();
 
+        // MQTT 5 puts the property block in the variable header, ahead of the topic filters.
+        let props_start = bytes.len();
+        let _props = extract_properties(&mut bytes, PropertyOwner::Subscribe)?;
+        payload_bytes -= props_start - bytes.len();
+
         while payload_bytes > 0 {
             let topic_filter = extract_mqtt_string(&mut bytes)?;
-            let requested_qos = bytes.get_u8();
+            let options = bytes.get_u8();
+            if options & 0b1100_0000 != 0 {
+                return Err(Error::InvalidSubscribeOptions(options));
+            }
             payload_bytes -= topic_filter.len() + 3;
             topics.push(SubscribeTopic {
                 topic_path: topic_filter,
-                qos: qos(requested_qos)?,
+                qos: qos(options & 0b0000_0011)?,
+                no_local: (options & 0b0000_0100) != 0,
+                retain_as_published: (options & 0b0000_1000) != 0,
+                retain_handling: retain_handling((options & 0b0011_0000) >> 4)?,
             });
         }
-        let _props = extract_properties(&mut bytes)?;
         let subscribe = match _props {
             Some(props) => {
+                // [MQTT-3.8.2.1.3]: it's a protocol error for a SUBSCRIBE to carry the
+                // Subscription Identifier more than once (unlike a forwarded PUBLISH).
+                if props.subscription_identifier.len() > 1 {
+                    return Err(Error::InvalidProperty);
+                }
+
                 let properties = Some(
                     SubscribeProperties {
-                        subscription_identifier: props.subscription_identifier,
-                        user_property: props.user_property,
+                        subscription_identifier: props.subscription_identifier.first().copied(),
+                        user_properties: props.user_properties,
                     }
                 );
                 Subscribe { pkid, topics, properties }
@@ -61,6 +96,9 @@ impl Subscribe {
         let topic = SubscribeTopic {
             topic_path: topic.into(),
             qos,
+            no_local: false,
+            retain_as_published: false,
+            retain_handling: RetainHandling::SendAtSubscribe,
         };
 
         Subscribe {
@@ -79,10 +117,67 @@ impl Subscribe {
     }
 
     pub fn add(&mut self, topic: String, qos: QoS) -> &mut Self {
-        let topic = SubscribeTopic { topic_path: topic, qos };
+        let topic = SubscribeTopic {
+            topic_path: topic,
+            qos,
+            no_local: false,
+            retain_as_published: false,
+            retain_handling: RetainHandling::SendAtSubscribe,
+        };
         self.topics.push(topic);
         self
     }
+
+    /// Like [`Subscribe::add`], but lets the caller set the MQTT v5 subscription options
+    /// (No Local, Retain As Published, Retain Handling) instead of defaulting them.
+    pub fn add_with_options(
+        &mut self,
+        topic: String,
+        qos: QoS,
+        no_local: bool,
+        retain_as_published: bool,
+        retain_handling: RetainHandling,
+    ) -> &mut Self {
+        let topic = SubscribeTopic {
+            topic_path: topic,
+            qos,
+            no_local,
+            retain_as_published,
+            retain_handling,
+        };
+        self.topics.push(topic);
+        self
+    }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut payload = BytesMut::new();
+        for topic in self.topics.iter() {
+            payload.extend_from_slice(&encode_utf_string(topic.topic_path.clone())?);
+            let options = (topic.qos as u8)
+                | ((topic.no_local as u8) << 2)
+                | ((topic.retain_as_published as u8) << 3)
+                | ((topic.retain_handling as u8) << 4);
+            payload.put_u8(options);
+        }
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b1000_0010); // SUBSCRIBE, reserved flags = 0b0010
+        packet.extend_from_slice(&encode_variable_byte((var_header.len() + payload.len()) as i32)?);
+        packet.extend_from_slice(&var_header);
+        packet.extend_from_slice(&payload);
+
+        Ok(packet.to_bytes())
+    }
 }
 
 impl fmt::Debug for Subscribe {
@@ -145,18 +240,139 @@ mod test_publish {
                     SubscribeTopic {
                         topic_path: "a/+".to_owned(),
                         qos: QoS::AtMostOnce,
+                        no_local: false,
+                        retain_as_published: false,
+                        retain_handling: RetainHandling::SendAtSubscribe,
                     },
                     SubscribeTopic {
                         topic_path: "#".to_owned(),
                         qos: QoS::AtLeastOnce,
+                        no_local: false,
+                        retain_as_published: false,
+                        retain_handling: RetainHandling::SendAtSubscribe,
                     },
                     SubscribeTopic {
                         topic_path: "a/b/c".to_owned(),
                         qos: QoS::ExactlyOnce,
+                        no_local: false,
+                        retain_as_published: false,
+                        retain_handling: RetainHandling::SendAtSubscribe,
                     }
                 ],
                 properties: None
             }
         );
     }
+
+    #[test]
+    fn subscribe_options_byte_is_decoded_correctly() {
+        let stream = &[
+            0b1000_0010,
+            9, // packet type, flags and remaining len
+            0x00,
+            0x01, // variable header. pkid = 1
+            0x00, // variable header. property length = 0
+            0x00,
+            0x03,
+            b'a',
+            b'/',
+            b'b', // payload. topic filter = 'a/b'
+            0b0010_1110, // payload. retain handling = DoNotSend, retain as published, no local, qos2
+        ];
+        let mut stream = BytesMut::from(&stream[..]);
+
+        let packet = mqtt_read(&mut stream, 100).unwrap();
+        let packet = match packet {
+            Packet::Subscribe(packet) => packet,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(
+            packet.topics[0],
+            SubscribeTopic {
+                topic_path: "a/b".to_owned(),
+                qos: QoS::ExactlyOnce,
+                no_local: true,
+                retain_as_published: true,
+                retain_handling: RetainHandling::DoNotSend,
+            }
+        );
+    }
+
+    #[test]
+    fn subscribe_options_byte_with_reserved_bits_set_is_rejected() {
+        let stream = &[
+            0b1000_0010,
+            9, // packet type, flags and remaining len
+            0x00,
+            0x01, // variable header. pkid = 1
+            0x00, // variable header. property length = 0
+            0x00,
+            0x03,
+            b'a',
+            b'/',
+            b'b', // payload. topic filter = 'a/b'
+            0b1000_0000, // payload. reserved bit 7 set, qos0
+        ];
+        let mut stream = BytesMut::from(&stream[..]);
+
+        let packet = mqtt_read(&mut stream, 100);
+        match packet {
+            Err(Error::InvalidSubscribeOptions(0b1000_0000)) => {}
+            packet => panic!("Expected InvalidSubscribeOptions, got = {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn subscribe_write_and_read_round_trips() {
+        let mut packet = Subscribe::new("a/b", QoS::ExactlyOnce);
+        packet.add("c/d".to_owned(), QoS::AtLeastOnce);
+        packet.pkid = 260;
+
+        let bytes = mqtt_write(Packet::Subscribe(packet.clone())).unwrap();
+        let mut stream = BytesMut::from(&bytes[..]);
+
+        let packet = mqtt_read(&mut stream, 100).unwrap();
+        let packet = match packet {
+            Packet::Subscribe(packet) => packet,
+            packet => panic!("Invalid packet = {:?}", packet),
+        };
+
+        assert_eq!(
+            packet,
+            Subscribe {
+                pkid: 260,
+                topics: vec![
+                    SubscribeTopic {
+                        topic_path: "a/b".to_owned(),
+                        qos: QoS::ExactlyOnce,
+                        no_local: false,
+                        retain_as_published: false,
+                        retain_handling: RetainHandling::SendAtSubscribe,
+                    },
+                    SubscribeTopic {
+                        topic_path: "c/d".to_owned(),
+                        qos: QoS::AtLeastOnce,
+                        no_local: false,
+                        retain_as_published: false,
+                        retain_handling: RetainHandling::SendAtSubscribe,
+                    },
+                ],
+                properties: None,
+            }
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn subscribe_json_round_trips() {
+        let mut packet = Subscribe::new("a/b", QoS::ExactlyOnce);
+        packet.add("c/d".to_owned(), QoS::AtLeastOnce);
+        packet.pkid = 260;
+
+        let json = serde_json::to_string(&packet).unwrap();
+        let read_back: Subscribe = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(read_back, packet);
+    }
 }
@@ -1,24 +1,45 @@
-use crate::Error;
+use crate::{encode_utf_string, encode_utf_string_pair, encode_variable_byte, subscribe_reason_code, Error, SubscribeReasonCode, Utf8Pair};
 use crate::FixedHeader;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use alloc::string::String;
-use crate::control::properties::extract_properties;
+use alloc::vec::Vec;
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct UnsubAckProperties {
     pub reason_string: Option<String>,
-    pub user_property: Option<String>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
+impl UnsubAckProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        if let Some(reason_string) = &self.reason_string {
+            props.put_u8(PropertyIdentifiers::REASON_STRING);
+            props.extend_from_slice(&encode_utf_string(reason_string.clone())?);
+        }
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct UnsubAck {
     pub pkid: u16,
+    pub reason_codes: Vec<SubscribeReasonCode>,
     pub properties: Option<UnsubAckProperties>,
 }
 
 impl UnsubAck {
     pub(crate) fn assemble(fixed_header: FixedHeader, mut bytes: Bytes) -> Result<Self, Error> {
-        if fixed_header.remaining_len != 2 {
+        if fixed_header.remaining_len < 2 {
             return Err(Error::PayloadSizeIncorrect);
         }
 
@@ -26,20 +47,53 @@ impl UnsubAck {
         bytes.advance(variable_header_index);
         let pkid = bytes.get_u16();
 
-        let _props = extract_properties(&mut bytes)?;
-        let unsuback = match _props {
-            Some(props) => {
-                let properties = Some(
-                    UnsubAckProperties {
-                        reason_string: props.reason_string,
-                        user_property: props.user_property,
-                    }
-                );
-                UnsubAck { pkid, properties }
-            }
-            None => UnsubAck { pkid, properties: None }
+        let _props = extract_properties(&mut bytes, PropertyOwner::UnsubAck)?;
+        let properties = match _props {
+            Some(props) => Some(
+                UnsubAckProperties {
+                    reason_string: props.reason_string,
+                    user_properties: props.user_properties,
+                }
+            ),
+            None => None,
         };
 
-        Ok(unsuback)
+        let mut reason_codes = Vec::new();
+        while bytes.has_remaining() {
+            reason_codes.push(subscribe_reason_code(bytes.get_u8())?);
+        }
+
+        Ok(UnsubAck { pkid, reason_codes, properties })
+    }
+}
+
+impl UnsubAck {
+    pub fn new(pkid: u16, reason_codes: Vec<SubscribeReasonCode>, properties: Option<UnsubAckProperties>) -> UnsubAck {
+        UnsubAck { pkid, reason_codes, properties }
+    }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+        var_header.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        var_header.extend_from_slice(&props);
+
+        let mut payload = BytesMut::new();
+        for reason_code in self.reason_codes.iter() {
+            payload.put_u8(*reason_code as u8);
+        }
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b1011_0000); // UNSUBACK, reserved flags = 0
+        packet.extend_from_slice(&encode_variable_byte((var_header.len() + payload.len()) as i32)?);
+        packet.extend_from_slice(&var_header);
+        packet.extend_from_slice(&payload);
+
+        Ok(packet.to_bytes())
     }
 }
@@ -1,14 +1,29 @@
-use crate::{extract_mqtt_string, Error, FixedHeader};
+use crate::{encode_utf_string_pair, encode_variable_byte, extract_mqtt_string, Error, FixedHeader, Utf8Pair};
 use alloc::string::String;
 use alloc::vec::Vec;
-use bytes::{Buf, Bytes};
-use crate::control::properties::extract_properties;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::control::properties::{extract_properties, PropertyIdentifiers, PropertyOwner};
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct UnsubscribeProperties {
-    pub user_property: Option<String>,
+    pub user_properties: Vec<Utf8Pair>,
 }
 
+impl UnsubscribeProperties {
+    pub(crate) fn disassemble(&self) -> Result<Bytes, Error> {
+        let mut props = BytesMut::new();
+        for user_property in &self.user_properties {
+            props.put_u8(PropertyIdentifiers::USER_PROPERTY);
+            props.extend_from_slice(&encode_utf_string_pair(user_property.clone())?);
+        }
+        Ok(props.to_bytes())
+    }
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Unsubscribe {
     pub pkid: u16,
@@ -30,12 +45,12 @@ impl Unsubscribe {
             topics.push(topic_filter);
         }
 
-        let _props = extract_properties(&mut bytes)?;
+        let _props = extract_properties(&mut bytes, PropertyOwner::Unsubscribe)?;
         let unsubscribe= match _props {
             Some(props)=>{
                 let properties = Some(
                     UnsubscribeProperties{
-                        user_property: props.user_property
+                        user_properties: props.user_properties
                     }
                 );
                 Unsubscribe { pkid, topics, properties }
@@ -46,3 +61,35 @@ impl Unsubscribe {
         Ok(unsubscribe)
     }
 }
+
+impl Unsubscribe {
+    pub fn new(pkid: u16, topics: Vec<String>, properties: Option<UnsubscribeProperties>) -> Unsubscribe {
+        Unsubscribe { pkid, topics, properties }
+    }
+
+    pub(crate) fn disassemble(self) -> Result<Bytes, Error> {
+        let props = match &self.properties {
+            Some(properties) => properties.disassemble()?,
+            None => Bytes::new(),
+        };
+
+        let mut var_header = BytesMut::new();
+        var_header.put_u16(self.pkid);
+
+        let mut payload = BytesMut::new();
+        for topic in self.topics.iter() {
+            payload.put_u16(topic.len() as u16);
+            payload.extend_from_slice(topic.as_bytes());
+        }
+        payload.extend_from_slice(&encode_variable_byte(props.len() as i32)?);
+        payload.extend_from_slice(&props);
+
+        let mut packet = BytesMut::new();
+        packet.put_u8(0b1010_0010); // UNSUBSCRIBE, reserved flags = 0b0010
+        packet.extend_from_slice(&encode_variable_byte((var_header.len() + payload.len()) as i32)?);
+        packet.extend_from_slice(&var_header);
+        packet.extend_from_slice(&payload);
+
+        Ok(packet.to_bytes())
+    }
+}
@@ -1,4 +1,7 @@
 use crate::control::*;
+use core::fmt;
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone)]
 pub enum Packet {
@@ -30,10 +33,23 @@ pub enum ConnectReturnCode {
     NotAuthorized,
 }
 
+/// Per-topic-filter reason code carried in SUBACK and UNSUBACK payloads.
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SubscribeReturnCodes {
-    Success(QoS),
-    Failure,
+#[repr(u8)]
+pub enum SubscribeReasonCode {
+    GrantedQoS0 = 0x00,
+    GrantedQoS1 = 0x01,
+    GrantedQoS2 = 0x02,
+    UnspecifiedError = 0x80,
+    ImplementationSpecificError = 0x83,
+    NotAuthorized = 0x87,
+    TopicFilterInvalid = 0x8F,
+    PacketIdentifierInUse = 0x91,
+    QuotaExceeded = 0x97,
+    SharedSubscriptionsNotSupported = 0x9E,
+    SubscriptionIdentifiersNotSupported = 0xA1,
+    WildcardSubscriptionsNotSupported = 0xA2,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -42,6 +58,7 @@ pub enum Protocol {
 }
 
 #[repr(u8)]
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
 pub enum QoS {
     AtMostOnce = 0,
@@ -68,3 +85,183 @@ pub enum PacketType {
     Disconnect,
     Auth,
 }
+
+/// Reason code carried in the CONNACK Variable Header.
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ConnAckReason {
+    Success = 0x00,
+    UnspecifiedError = 0x80,
+    MalformedPacket = 0x81,
+    ProtocolError = 0x82,
+    ImplementationSpecificError = 0x83,
+    UnsupportedProtocolVersion = 0x84,
+    ClientIdentifierNotValid = 0x85,
+    BadUserNameOrPassword = 0x86,
+    NotAuthorized = 0x87,
+    ServerUnavailable = 0x88,
+    ServerBusy = 0x89,
+    Banned = 0x8A,
+    BadAuthenticationMethod = 0x8C,
+    TopicNameInvalid = 0x90,
+    PacketTooLarge = 0x95,
+    QuotaExceeded = 0x97,
+    PayloadFormatInvalid = 0x99,
+    RetainNotSupported = 0x9A,
+    QoSNotSupported = 0x9B,
+    UseAnotherServer = 0x9C,
+    ServerMoved = 0x9D,
+    ConnectionRateExceeded = 0x9F,
+}
+
+impl ConnAckReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            ConnAckReason::Success => "The Connection is accepted",
+            ConnAckReason::UnspecifiedError => "The Server does not wish to reveal the reason for the failure, or none of the other Reason Codes apply",
+            ConnAckReason::MalformedPacket => "Data within the CONNECT packet could not be correctly parsed",
+            ConnAckReason::ProtocolError => "Data in the CONNECT packet does not conform to this specification",
+            ConnAckReason::ImplementationSpecificError => "The CONNECT is valid but is not accepted by this Server",
+            ConnAckReason::UnsupportedProtocolVersion => "The Server does not support the version of the MQTT protocol requested by the Client",
+            ConnAckReason::ClientIdentifierNotValid => "The Client Identifier is a valid string but is not allowed by the Server",
+            ConnAckReason::BadUserNameOrPassword => "The Server does not accept the User Name or Password specified by the Client",
+            ConnAckReason::NotAuthorized => "The Client is not authorized to connect",
+            ConnAckReason::ServerUnavailable => "The MQTT Server is not available",
+            ConnAckReason::ServerBusy => "The Server is busy. Try again later",
+            ConnAckReason::Banned => "This Client has been banned by administrative action",
+            ConnAckReason::BadAuthenticationMethod => "The authentication method is not supported or does not match the authentication method currently in use",
+            ConnAckReason::TopicNameInvalid => "The Will Topic Name is not malformed, but is not accepted by this Server",
+            ConnAckReason::PacketTooLarge => "The CONNECT packet exceeded the maximum permissible size",
+            ConnAckReason::QuotaExceeded => "An implementation or administrative imposed limit has been exceeded",
+            ConnAckReason::PayloadFormatInvalid => "The Will Payload does not match the specified Payload Format Indicator",
+            ConnAckReason::RetainNotSupported => "The Server does not support retained messages, and Will Retain was set to 1",
+            ConnAckReason::QoSNotSupported => "The Server does not support the QoS set in Will QoS",
+            ConnAckReason::UseAnotherServer => "The Client should temporarily use another server",
+            ConnAckReason::ServerMoved => "The Client should permanently use another server",
+            ConnAckReason::ConnectionRateExceeded => "The connection rate limit has been exceeded",
+        }
+    }
+}
+
+impl fmt::Display for ConnAckReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Reason code carried in the PUBACK Variable Header.
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PubAckReason {
+    Success = 0x00,
+    NoMatchingSubscribers = 0x10,
+    UnspecifiedError = 0x80,
+    ImplementationSpecificError = 0x83,
+    NotAuthorized = 0x87,
+    TopicNameInvalid = 0x90,
+    PacketIdentifierInUse = 0x91,
+    QuotaExceeded = 0x97,
+    PayloadFormatInvalid = 0x99,
+}
+
+impl PubAckReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            PubAckReason::Success => "The message is accepted. Publication of the QoS 1 message proceeds",
+            PubAckReason::NoMatchingSubscribers => "The message is accepted but there are no subscribers",
+            PubAckReason::UnspecifiedError => "The receiver does not wish to reveal the reason for the failure, or none of the other Reason Codes apply",
+            PubAckReason::ImplementationSpecificError => "The PUBLISH is valid but is not accepted by this receiver",
+            PubAckReason::NotAuthorized => "The PUBLISH is not authorized",
+            PubAckReason::TopicNameInvalid => "The Topic Name is not malformed, but is not accepted by this receiver",
+            PubAckReason::PacketIdentifierInUse => "The Packet Identifier is already in use",
+            PubAckReason::QuotaExceeded => "An implementation or administrative imposed limit has been exceeded",
+            PubAckReason::PayloadFormatInvalid => "The payload format does not match the specified Payload Format Indicator",
+        }
+    }
+}
+
+impl fmt::Display for PubAckReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Reason code carried in the DISCONNECT Variable Header.
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DisconnectReason {
+    NormalDisconnection = 0x00,
+    DisconnectWithWillMessage = 0x04,
+    UnspecifiedError = 0x80,
+    MalformedPacket = 0x81,
+    ProtocolError = 0x82,
+    ImplementationSpecificError = 0x83,
+    NotAuthorized = 0x87,
+    ServerBusy = 0x89,
+    ServerShuttingDown = 0x8B,
+    KeepAliveTimeout = 0x8D,
+    SessionTakenOver = 0x8E,
+    TopicFilterInvalid = 0x8F,
+    TopicNameInvalid = 0x90,
+    ReceiveMaximumExceeded = 0x93,
+    TopicAliasInvalid = 0x94,
+    PacketTooLarge = 0x95,
+    MessageRateTooHigh = 0x96,
+    QuotaExceeded = 0x97,
+    AdministrativeAction = 0x98,
+    PayloadFormatInvalid = 0x99,
+    RetainNotSupported = 0x9A,
+    QoSNotSupported = 0x9B,
+    UseAnotherServer = 0x9C,
+    ServerMoved = 0x9D,
+    SharedSubscriptionsNotSupported = 0x9E,
+    ConnectionRateExceeded = 0x9F,
+    MaximumConnectTime = 0xA0,
+    SubscriptionIdentifiersNotSupported = 0xA1,
+    WildcardSubscriptionsNotSupported = 0xA2,
+}
+
+impl DisconnectReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            DisconnectReason::NormalDisconnection => "Close the connection normally. Do not send the Will Message",
+            DisconnectReason::DisconnectWithWillMessage => "The Client wishes to disconnect but requires that the Server also publishes its Will Message",
+            DisconnectReason::UnspecifiedError => "The Connection is closed but the sender does not wish to reveal the reason, or none of the other Reason Codes apply",
+            DisconnectReason::MalformedPacket => "The received packet does not conform to this specification",
+            DisconnectReason::ProtocolError => "An unexpected or out of order packet was received",
+            DisconnectReason::ImplementationSpecificError => "The packet received is valid but cannot be processed by this implementation",
+            DisconnectReason::NotAuthorized => "The request is not authorized",
+            DisconnectReason::ServerBusy => "The Server is busy and cannot continue processing requests from this Client",
+            DisconnectReason::ServerShuttingDown => "The Server is shutting down",
+            DisconnectReason::KeepAliveTimeout => "The Connection is closed because no packet has been received for 1.5 times the Keepalive time",
+            DisconnectReason::SessionTakenOver => "Another Connection using the same ClientID has connected, causing this Connection to be closed",
+            DisconnectReason::TopicFilterInvalid => "The Topic Filter is correctly formed, but is not accepted by this Sever",
+            DisconnectReason::TopicNameInvalid => "The Topic Name is correctly formed, but is not accepted by this Client or Server",
+            DisconnectReason::ReceiveMaximumExceeded => "The Client or Server has received more than Receive Maximum publication for which it has not sent PUBACK or PUBCOMP",
+            DisconnectReason::TopicAliasInvalid => "The Client or Server has received a PUBLISH packet containing a Topic Alias which is greater than the Maximum Topic Alias it sent in the CONNECT or CONNACK packet",
+            DisconnectReason::PacketTooLarge => "The packet size is greater than Maximum Packet Size for this Client or Server",
+            DisconnectReason::MessageRateTooHigh => "The received data rate is too high",
+            DisconnectReason::QuotaExceeded => "An implementation or administrative imposed limit has been exceeded",
+            DisconnectReason::AdministrativeAction => "The Connection is closed due to an administrative action",
+            DisconnectReason::PayloadFormatInvalid => "The payload format does not match the one specified by the Payload Format Indicator",
+            DisconnectReason::RetainNotSupported => "The Server has does not support retained messages",
+            DisconnectReason::QoSNotSupported => "The Client specified a QoS greater than the QoS specified in a Maximum QoS in the CONNACK",
+            DisconnectReason::UseAnotherServer => "The Client should temporarily change its Server",
+            DisconnectReason::ServerMoved => "The Server is moved and the Client should permanently change its server location",
+            DisconnectReason::SharedSubscriptionsNotSupported => "The Server does not support Shared Subscriptions",
+            DisconnectReason::ConnectionRateExceeded => "This connection is closed because the connection rate is too high",
+            DisconnectReason::MaximumConnectTime => "The maximum connection time authorized for this connection has been exceeded",
+            DisconnectReason::SubscriptionIdentifiersNotSupported => "The Server does not support Subscription Identifiers",
+            DisconnectReason::WildcardSubscriptionsNotSupported => "The Server does not support Wildcard Subscriptions",
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
@@ -11,12 +11,26 @@ cfg_if! {
             InvalidProtocol,
             #[error("Invalid protocol level `{0}`")]
             InvalidProtocolLevel(u8),
+            #[error("Malformed connect flags `{0}`. Reserved bit must be 0, and will_qos/will_retain must be 0 when the will flag is unset")]
+            MalformedConnectFlags(u8),
             #[error("Incorrect packet format")]
             IncorrectPacketFormat,
             #[error("Unsupported Packet type `{0}`")]
             InvalidPacketType(u8),
             #[error("Unsupported QoS `{0}`")]
             InvalidQoS(u8),
+            #[error("Invalid retain handling `{0}`")]
+            InvalidRetainHandling(u8),
+            #[error("Invalid subscribe reason code `{0}`")]
+            InvalidSubscribeReasonCode(u8),
+            #[error("Invalid subscribe options `{0}`. Reserved bits 6-7 must be 0")]
+            InvalidSubscribeOptions(u8),
+            #[error("Invalid puback reason code `{0}`")]
+            InvalidPubAckReasonCode(u8),
+            #[error("Invalid disconnect reason code `{0}`")]
+            InvalidDisconnectReasonCode(u8),
+            #[error("Invalid topic alias `{0}`. Must be nonzero and within the negotiated Topic Alias Maximum (maps to ReasonCode::TOPIC_ALIAS_INVALID)")]
+            InvalidTopicAlias(u16),
             #[error("Invalid packet identifier = 0")]
             PacketIdZero,
             #[error("Payload size incorrect")]
@@ -35,6 +49,12 @@ cfg_if! {
             BoundaryCrossed,
             #[error("EOF. Not enough data in buffer")]
             UnexpectedEof,
+            #[error("Unrecognized or out-of-place MQTT v5 property identifier")]
+            InvalidProperty,
+            #[error("Property not permitted on this packet type")]
+            InvalidPropertyForPacket,
+            #[error("Malformed variable byte integer")]
+            MalformedVariableByteInteger,
             #[error("I/O")]
             Io(#[from] std::io::Error),
         }
@@ -43,9 +63,16 @@ cfg_if! {
             InvalidConnectReturnCode(u8),
             InvalidProtocol,
             InvalidProtocolLevel(u8),
+            MalformedConnectFlags(u8),
             IncorrectPacketFormat,
             InvalidPacketType(u8),
             InvalidQoS(u8),
+            InvalidRetainHandling(u8),
+            InvalidSubscribeReasonCode(u8),
+            InvalidSubscribeOptions(u8),
+            InvalidPubAckReasonCode(u8),
+            InvalidDisconnectReasonCode(u8),
+            InvalidTopicAlias(u16),
             PacketIdZero,
             PayloadSizeIncorrect,
             PayloadTooLong,
@@ -55,6 +82,9 @@ cfg_if! {
             BoundaryCrossed,
             MalformedRemainingLength,
             UnexpectedEof,
+            InvalidProperty,
+            InvalidPropertyForPacket,
+            MalformedVariableByteInteger,
         }
     }
 }
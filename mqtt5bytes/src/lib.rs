@@ -6,6 +6,8 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "auth")]
+mod auth;
 #[cfg(feature = "std")]
 mod codec;
 mod control;
@@ -20,6 +22,8 @@ mod write;
 
 use alloc::string::String;
 use bytes::{Buf, Bytes};
+#[cfg(feature = "auth")]
+pub use auth::*;
 #[cfg(feature = "std")]
 pub use codec::*;
 pub use control::*;
@@ -80,6 +80,94 @@ fn parse_fixed_header(mut stream: BytesMut) -> Result<(u8, usize), Error> {
     Ok((byte1, rl as usize))
 }
 
+/// Like [`parse_fixed_header`], but tolerates a buffer that doesn't yet hold a complete
+/// Remaining Length field, returning `Ok(None)` instead of `Err(Error::UnexpectedEof)`.
+/// Returns the decoded byte 1, remaining length and the fixed header's on-wire length.
+fn try_parse_fixed_header(stream: &[u8]) -> Result<Option<(u8, usize, usize)>, Error> {
+    if stream.is_empty() {
+        return Ok(None);
+    }
+
+    let byte1 = stream[0];
+    let mut value: usize = 0;
+    let mut multiplier: usize = 1;
+
+    for (i, byte) in stream[1..].iter().enumerate() {
+        value += (*byte as usize & 0x7F) * multiplier;
+        if (*byte & 0x80) == 0 {
+            return Ok(Some((byte1, value, i + 2)));
+        }
+        multiplier *= 128;
+        if i == 3 {
+            return Err(Error::MalformedRemainingLength);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Non-blocking counterpart to [`mqtt_read`] for callers driving a streaming transport
+/// (e.g. the `codec` module). Returns `Ok(None)` when `stream` doesn't yet hold a
+/// complete fixed header or a complete packet, leaving `stream` untouched in that case.
+/// Bytes are only consumed via `split_to` once a whole packet is available. Malformed
+/// data (bad packet type, reserved QoS, pkid zero, etc.) still returns `Err` immediately.
+pub fn mqtt_read_incremental(
+    stream: &mut BytesMut,
+    max_payload_size: usize,
+) -> Result<Option<Packet>, Error> {
+    let (byte1, remaining_len, header_len) = match try_parse_fixed_header(stream)? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    if remaining_len > max_payload_size {
+        return Err(Error::PayloadSizeLimitExceeded);
+    }
+
+    let len = header_len + remaining_len;
+    if stream.len() < len {
+        stream.reserve(len - stream.len());
+        return Ok(None);
+    }
+
+    let mut packet = stream.split_to(len);
+    let control_type = packet_type(byte1 >> 4)?;
+
+    if remaining_len == 0 {
+        return match control_type {
+            PacketType::PingReq => Ok(Some(Packet::PingReq)),
+            PacketType::PingResp => Ok(Some(Packet::PingResp)),
+            _ => Err(Error::PayloadRequired),
+        };
+    }
+
+    let fixed_header = FixedHeader {
+        byte1,
+        header_len,
+        remaining_len,
+    };
+
+    let packet = match control_type {
+        PacketType::Connect => Packet::Connect(Connect::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::ConnAck => Packet::ConnAck(ConnAck::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::Publish => Packet::Publish(Publish::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::PubAck => Packet::PubAck(PubAck::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::PubRec => Packet::PubRec(PubRec::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::PubRel => Packet::PubRel(PubRel::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::PubComp => Packet::PubComp(PubComp::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::Subscribe => Packet::Subscribe(Subscribe::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::SubAck => Packet::SubAck(SubAck::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::Unsubscribe => Packet::Unsubscribe(Unsubscribe::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::UnsubAck => Packet::UnsubAck(UnsubAck::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::PingReq => Packet::PingReq,
+        PacketType::PingResp => Packet::PingResp,
+        PacketType::Disconnect => Packet::Disconnect(Disconnect::assemble(fixed_header, packet.to_bytes())?),
+        PacketType::Auth => Packet::Auth(Auth::assemble(fixed_header, packet.to_bytes())?),
+    };
+
+    Ok(Some(packet))
+}
+
 fn header_len(remaining_len: usize) -> usize {
     if remaining_len >= 2_097_152 {
         4 + 1
@@ -94,7 +182,7 @@ fn header_len(remaining_len: usize) -> usize {
 
 #[cfg(test)]
 mod test {
-    use super::{mqtt_read, parse_fixed_header};
+    use super::{mqtt_read, mqtt_read_incremental, parse_fixed_header};
     use crate::{Error, Packet};
     use alloc::vec;
     use pretty_assertions::assert_eq;
@@ -142,4 +230,53 @@ mod test {
         assert_eq!(remaining_len, 268_435_455);
         data.clear();
     }
+
+    #[test]
+    fn incremental_read_waits_for_a_complete_packet() {
+        let full = &[0b1100_0000, 0x00]; // PINGREQ
+        let mut stream = BytesMut::new();
+
+        // Not even the fixed header's remaining length byte is here yet
+        stream.extend_from_slice(&full[..1]);
+        assert!(mqtt_read_incremental(&mut stream, 100).unwrap().is_none());
+
+        // Now the whole packet is available
+        stream.extend_from_slice(&full[1..]);
+        assert!(matches!(mqtt_read_incremental(&mut stream, 100).unwrap(), Some(Packet::PingReq)));
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn incremental_read_leaves_a_partial_subscribe_packet_untouched() {
+        let stream = &[
+            0b1000_0010,
+            9, // packet type, flags and remaining len
+            0x00,
+            0x01, // variable header. pkid = 1
+            0x00, // variable header. property length = 0
+            0x00,
+            0x03,
+            b'a',
+            b'/',
+            b'b', // payload. topic filter = 'a/b'
+            0x00, // payload. options byte
+        ];
+
+        let mut partial = BytesMut::from(&stream[..stream.len() - 1]);
+        assert!(mqtt_read_incremental(&mut partial, 100).unwrap().is_none());
+        assert_eq!(partial.len(), stream.len() - 1);
+
+        partial.extend_from_slice(&stream[stream.len() - 1..]);
+        let packet = mqtt_read_incremental(&mut partial, 100).unwrap();
+        assert!(matches!(packet, Some(Packet::Subscribe(_))));
+    }
+
+    #[test]
+    fn incremental_read_still_errors_on_malformed_data() {
+        let mut stream = BytesMut::from(&[0b1111_0000, 0x00][..]); // AUTH with zero remaining length, which AUTH doesn't allow
+        match mqtt_read_incremental(&mut stream, 100) {
+            Err(Error::PayloadRequired) => {}
+            other => panic!("Expected PayloadRequired, got = {:?}", other),
+        }
+    }
 }
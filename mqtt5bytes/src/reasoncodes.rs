@@ -56,7 +56,6 @@ mod test_reason_code {
 
     #[test]
     fn reason_code_assertion() {
-        let reason_code: ReasonCode;
         let value1: u8 = 159;
         assert_eq!(value1, ReasonCode::CONNECTION_RATE_EXCEEDED);
         assert_ne!(value1, ReasonCode::WILDCARD_SUBSCRIPTION_NOT_SUPPORTED);
@@ -0,0 +1,117 @@
+use crate::{Error, QoS};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+#[cfg(feature = "derive")]
+use serde::{Serialize, Deserialize};
+
+/// Retain Handling option carried in the MQTT v5 SUBSCRIBE options byte.
+/// [MQTT-3.8.3.1] controls whether the server sends retained messages for a
+/// topic filter at the time of subscribing.
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RetainHandling {
+    /// Send retained messages at the time of the subscribe.
+    SendAtSubscribe = 0,
+    /// Send retained messages at subscribe only if the subscription did not already exist.
+    SendAtSubscribeIfNew = 1,
+    /// Do not send retained messages at the time of the subscribe.
+    DoNotSend = 2,
+}
+
+#[cfg_attr(feature = "derive", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscribeTopic {
+    pub topic_path: String,
+    pub qos: QoS,
+    /// Don't forward publishes back to the subscribing client when it's also the publisher.
+    pub no_local: bool,
+    /// Keep the RETAIN flag of a publish as it was received, instead of clearing it on delivery.
+    pub retain_as_published: bool,
+    pub retain_handling: RetainHandling,
+}
+
+/// Tracks the Topic Alias mappings (`PropertyIdentifiers::TOPIC_ALIAS`) negotiated for one
+/// direction of a connection. A PUBLISH may carry an alias alongside its real topic name to
+/// record the mapping, then omit the topic name on later PUBLISHes and rely on the alias alone -
+/// one map is needed per direction since inbound and outbound aliases are independent
+/// (MQTT-3.3.2.3.4).
+#[derive(Debug, Clone, Default)]
+pub struct TopicAliasMap {
+    aliases: BTreeMap<u16, String>,
+    maximum: u16,
+}
+
+impl TopicAliasMap {
+    /// `maximum` is the Topic Alias Maximum negotiated for this direction via
+    /// `topic_alias_maximum` (0 means the peer does not accept any aliases).
+    pub fn new(maximum: u16) -> TopicAliasMap {
+        TopicAliasMap { aliases: BTreeMap::new(), maximum }
+    }
+
+    /// Resolve a PUBLISH's topic name given the `alias` and `topic_name` found on the wire.
+    ///
+    /// When `topic_name` is non-empty, the mapping is recorded (or updated) and `topic_name` is
+    /// returned unchanged. When `topic_name` is empty, the topic previously recorded for `alias`
+    /// is returned. Returns `Error::InvalidTopicAlias` (maps to `ReasonCode::TOPIC_ALIAS_INVALID`)
+    /// when `alias` is 0, exceeds `maximum`, or an empty `topic_name` has no prior mapping.
+    pub fn resolve(&mut self, alias: u16, topic_name: &str) -> Result<String, Error> {
+        if alias == 0 || alias > self.maximum {
+            return Err(Error::InvalidTopicAlias(alias));
+        }
+
+        if !topic_name.is_empty() {
+            self.aliases.insert(alias, String::from(topic_name));
+            return Ok(String::from(topic_name));
+        }
+
+        self.aliases
+            .get(&alias)
+            .cloned()
+            .ok_or(Error::InvalidTopicAlias(alias))
+    }
+}
+
+#[cfg(test)]
+mod test_topic_alias_map {
+    use crate::{Error, TopicAliasMap};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn records_and_then_resolves_an_alias() {
+        let mut aliases = TopicAliasMap::new(10);
+
+        assert_eq!(aliases.resolve(1, "a/b").unwrap(), "a/b");
+        assert_eq!(aliases.resolve(1, "").unwrap(), "a/b");
+    }
+
+    #[test]
+    fn rejects_alias_zero() {
+        let mut aliases = TopicAliasMap::new(10);
+
+        match aliases.resolve(0, "a/b") {
+            Err(Error::InvalidTopicAlias(0)) => {}
+            other => panic!("Expected InvalidTopicAlias(0), got = {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_alias_beyond_the_negotiated_maximum() {
+        let mut aliases = TopicAliasMap::new(1);
+
+        match aliases.resolve(2, "a/b") {
+            Err(Error::InvalidTopicAlias(2)) => {}
+            other => panic!("Expected InvalidTopicAlias(2), got = {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_an_empty_topic_name_with_no_prior_mapping() {
+        let mut aliases = TopicAliasMap::new(10);
+
+        match aliases.resolve(1, "") {
+            Err(Error::InvalidTopicAlias(1)) => {}
+            other => panic!("Expected InvalidTopicAlias(1), got = {:?}", other),
+        }
+    }
+}
@@ -5,6 +5,19 @@ use bytes::Bytes;
 pub fn mqtt_write(packet: Packet) -> Result<Bytes, Error> {
     match packet {
         Packet::Connect(pkt) => pkt.disassemble(),
-        _ => Err(Error::InvalidPacketType(0))
+        Packet::ConnAck(pkt) => pkt.disassemble(),
+        Packet::Publish(pkt) => pkt.disassemble(),
+        Packet::PubAck(pkt) => pkt.disassemble(),
+        Packet::PubRec(pkt) => pkt.disassemble(),
+        Packet::PubRel(pkt) => pkt.disassemble(),
+        Packet::PubComp(pkt) => pkt.disassemble(),
+        Packet::Subscribe(pkt) => pkt.disassemble(),
+        Packet::SubAck(pkt) => pkt.disassemble(),
+        Packet::Unsubscribe(pkt) => pkt.disassemble(),
+        Packet::UnsubAck(pkt) => pkt.disassemble(),
+        Packet::PingReq => Ok(Bytes::from_static(&[0b1100_0000, 0x00])),
+        Packet::PingResp => Ok(Bytes::from_static(&[0b1101_0000, 0x00])),
+        Packet::Disconnect(pkt) => pkt.disassemble(),
+        Packet::Auth(pkt) => pkt.disassemble(),
     }
 }
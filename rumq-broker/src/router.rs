@@ -1,5 +1,6 @@
 use derive_more::From;
-use rumq_core::mqtt4::{has_wildcards, matches, publish, QoS, Packet, Connect, Publish, Subscribe, SubscribeTopic, Unsubscribe};
+use mqtt5bytes::TopicAliasMap;
+use rumq_core::mqtt4::{has_wildcards, matches, publish, QoS, Packet, Connect, Publish, Subscribe, SubAck, SubscribeTopic, Unsubscribe};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::select;
@@ -9,14 +10,21 @@ use tokio::stream::StreamExt;
 use std::collections::{HashMap, VecDeque};
 use std::mem;
 use std::fmt;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::state::{self, MqttState};
 
+mod commitlog;
+
+use commitlog::{CommitLog, RetentionPolicy};
+
 #[derive(Debug, From)]
 pub enum Error {
     State(state::Error),
     AllSendersDown,
     Mpsc(TrySendError<RouterMessage>),
+    TopicAlias(mqtt5bytes::Error),
 }
 
 /// Router message to orchestrate data between connections. We can also
@@ -56,29 +64,134 @@ impl fmt::Debug for Connection {
     }
 }
 
+// MQTT v5 Receive Maximum (MQTT-3.1.2.11.3) caps how many QoS 1/2 publishes may be in flight to
+// a client, unacknowledged, at once. `rumq_core::mqtt4::Connect` is MQTT 3.1.1 and carries no
+// properties, so there's nowhere yet to read a client-negotiated value from at connect time -
+// default to the spec's implicit maximum until CONNECT property support lands here.
+const DEFAULT_SEND_CREDIT: u16 = 65535;
+
+// MQTT v5 Topic Alias (MQTT-3.3.2.3.4) lets a PUBLISH omit its topic name in favor of a small
+// numeric alias established on an earlier PUBLISH. Same story as `DEFAULT_SEND_CREDIT` above:
+// `rumq_core::mqtt4::Connect` carries no `topic_alias_maximum` property to read a client's
+// negotiated limit from, so every connection starts with no aliases accepted until CONNECT
+// property support lands here.
+const DEFAULT_TOPIC_ALIAS_MAXIMUM: u16 = 0;
+
 #[derive(Debug)]
 struct ActiveConnection {
-    pub state: MqttState,
+    // `None` for an in-process subscriber registered via `Router::subscribe_internal` - it has
+    // no network socket, so there's no MQTT session state (keep-alive, will, acks) to track.
+    pub state: Option<MqttState>,
     pub retained: Vec<Publish>,
+    // In `RetainMode::SyncWait`, retained publishes staged here per topic until this
+    // connection's live cursor for that topic reaches the log length snapshotted at
+    // subscribe time - i.e. until it's read everything that predates the subscription.
+    pending_retained:       HashMap<String, (usize, Vec<Publish>)>,
     concrete_subscriptions: HashMap<String, Subscription>,
     wild_subscriptions:     HashMap<String, Subscription>,
+    backpressure: Backpressure,
+    // Remaining number of QoS 1/2 publishes this connection may have unacknowledged in flight.
+    // Decremented in `Router::route` for each QoS 1/2 publish forwarded, replenished in
+    // `Router::handle_incoming_packet` when the matching PUBACK/PUBCOMP arrives. QoS 0
+    // publishes don't consume credit since they're never acknowledged.
+    send_credit: u16,
+    // Last time `Router::handle_incoming_packet` saw any packet on this connection (PINGREQ
+    // included). Compared against `keep_alive` on each tick to sweep out silently dead peers.
+    last_activity: Instant,
+    // The (possibly server-clamped) keep-alive negotiated at connect time. `None` for an
+    // in-process subscriber, which has no liveness to police, and for a real client that
+    // requested 0, which disables the keep-alive timeout entirely (MQTT-3.1.2.10).
+    keep_alive: Option<Duration>,
+    // Inbound topic-alias table for this connection (MQTT-3.3.2.3.4) - resolved against on every
+    // publish in `Router::fill_and_track` via `resolve_topic_alias`.
+    topic_aliases: TopicAliasMap,
     tx: Sender<RouterMessage>
 }
 
 impl ActiveConnection {
-    pub fn new(tx: Sender<RouterMessage>, state: MqttState) -> ActiveConnection {
+    pub fn new(tx: Sender<RouterMessage>, state: MqttState, keep_alive: Option<Duration>) -> ActiveConnection {
+        ActiveConnection {
+            state: Some(state),
+            retained: Vec::new(),
+            pending_retained: HashMap::new(),
+            concrete_subscriptions: HashMap::new(),
+            wild_subscriptions: HashMap::new(),
+            backpressure: Backpressure::new(),
+            send_credit: DEFAULT_SEND_CREDIT,
+            last_activity: Instant::now(),
+            keep_alive,
+            topic_aliases: TopicAliasMap::new(DEFAULT_TOPIC_ALIAS_MAXIMUM),
+            tx
+        }
+    }
+
+    /// Registers a virtual, socket-less connection: subscriptions flow through the normal
+    /// `route` path and land on `tx`, but there's no `MqttState` session behind it.
+    pub fn new_internal(tx: Sender<RouterMessage>) -> ActiveConnection {
         ActiveConnection {
-            state,
+            state: None,
             retained: Vec::new(),
+            pending_retained: HashMap::new(),
             concrete_subscriptions: HashMap::new(),
             wild_subscriptions: HashMap::new(),
+            backpressure: Backpressure::new(),
+            send_credit: DEFAULT_SEND_CREDIT,
+            last_activity: Instant::now(),
+            keep_alive: None,
+            topic_aliases: TopicAliasMap::new(DEFAULT_TOPIC_ALIAS_MAXIMUM),
             tx
         }
     }
 
-    pub fn add_to_subscriptions(&mut self, subscribe: Subscribe, retained_publishes: Vec<Publish>) {
-        self.retained.extend(retained_publishes);
+    /// Splits `publishes` at the longest prefix whose QoS 1/2 entries fit within
+    /// `self.send_credit`, decrementing `send_credit` by however many made the cut, and returns
+    /// `(prefix, remainder)`. QoS 0 entries never count against credit. Splitting rather than
+    /// filtering keeps the prefix contiguous, which callers rely on to advance a single offset
+    /// cursor by `prefix.len()` - the remainder stays unread and is retried next tick.
+    fn apply_send_credit(&mut self, mut publishes: Vec<Publish>) -> (Vec<Publish>, Vec<Publish>) {
+        let mut spent = 0u16;
+        let mut cutoff = publishes.len();
+        for (i, publish) in publishes.iter().enumerate() {
+            if publish.qos == QoS::AtMostOnce {
+                continue;
+            }
+            if spent >= self.send_credit {
+                cutoff = i;
+                break;
+            }
+            spent += 1;
+        }
+        self.send_credit -= spent;
+        let remainder = publishes.split_off(cutoff);
+        (publishes, remainder)
+    }
+
+    /// The (possibly server-clamped) keep-alive this connection was registered with, in seconds,
+    /// as CONNACK's `server_keep_alive` property should echo back to the client so it adopts the
+    /// broker's limit (MQTT-3.1.2.10 / MQTT-3.2.2.3.16) - see the NOTE in `Router::handle_connect`
+    /// for why nothing calls this yet. `None` mirrors `keep_alive` itself: no timeout in force,
+    /// either because the client requested 0 or because this is an in-process subscriber.
+    fn server_keep_alive(&self) -> Option<u16> {
+        self.keep_alive.map(|d| d.as_secs() as u16)
+    }
+
+    /// Resolves a PUBLISH's effective topic name against this connection's inbound topic-alias
+    /// table (MQTT-3.3.2.3.4). `alias` is `None` for a PUBLISH that carries no Topic Alias
+    /// property at all, which passes `topic_name` through unchanged; `Some(alias)` records or
+    /// looks up the mapping per `TopicAliasMap::resolve`.
+    fn resolve_topic_alias(&mut self, alias: Option<u16>, topic_name: &str) -> Result<String, Error> {
+        match alias {
+            Some(alias) => Ok(self.topic_aliases.resolve(alias, topic_name)?),
+            None => Ok(topic_name.to_owned()),
+        }
+    }
+
+    pub fn add_to_subscriptions(&mut self, subscribe: Subscribe) {
         // Each subscribe message can send multiple topics to subscribe to. handle dupicates
+        // NOTE: `subscribe.topics` here is `rumq_core::mqtt4::SubscribeTopic`, which only
+        // carries a topic path and QoS. MQTT v5's No Local and Retain As Published
+        // subscription options (see mqtt5bytes::SubscribeTopic) have no home on this type,
+        // so they can't be honored until the broker is moved onto the v5 subscribe packet.
         for topic in subscribe.topics {
             let mut filter = topic.topic_path.clone();
             let qos = topic.qos;
@@ -195,13 +308,31 @@ impl ActiveConnection {
 
 #[derive(Debug)]
 struct InactiveConnection {
-    pub state: MqttState
+    pub state: Option<MqttState>,
+    // Carried over from `ActiveConnection` when a persistent session goes inactive.
+    // `handle_connect` does not restore them: a reconnect removes the session from
+    // `inactive_connections` without reactivating it, so these cursors are discarded.
+    concrete_subscriptions: HashMap<String, Subscription>,
+    // When this session must be permanently dropped by `Router::sweep_expired_sessions`.
+    // `None` means the session is retained until broker restart (MQTT5's "absent Session
+    // Expiry Interval" behavior).
+    expires_at: Option<Instant>,
 }
 
 impl InactiveConnection {
-    pub fn new(state: MqttState) -> InactiveConnection {
+    pub fn new(state: MqttState, expires_at: Option<Instant>) -> InactiveConnection {
         InactiveConnection {
-            state,
+            state: Some(state),
+            concrete_subscriptions: HashMap::new(),
+            expires_at,
+        }
+    }
+
+    pub fn from_active(connection: ActiveConnection, expires_at: Option<Instant>) -> InactiveConnection {
+        InactiveConnection {
+            state: connection.state,
+            concrete_subscriptions: connection.concrete_subscriptions,
+            expires_at,
         }
     }
 }
@@ -209,17 +340,234 @@ impl InactiveConnection {
 #[derive(Debug, Clone)]
 struct Subscription {
     qos: QoS,
+    // Next unread offset into `Router::commitlog[topic]`, keyed by the concrete topic a
+    // publish arrived on. A concrete-filter subscription only ever has one entry here
+    // (filter == topic); a wildcard-filter subscription tracks one cursor per topic it
+    // has matched so far, since a single filter can fan out over many commitlog topics.
+    // Only advanced once the connection's `tx` has actually accepted the batch.
+    offsets: HashMap<String, usize>,
 }
 
 impl Subscription {
     pub fn new(qos: QoS) -> Subscription {
         Subscription {
             qos,
+            offsets: HashMap::new(),
+        }
+    }
+
+    fn offset(&self, topic: &str) -> usize {
+        *self.offsets.get(topic).unwrap_or(&0)
+    }
+
+    fn advance(&mut self, topic: &str, count: usize) {
+        *self.offsets.entry(topic.to_owned()).or_insert(0) += count;
+    }
+
+    /// Shifts a cursor back by `count` after `compact_commitlog` has drained that many
+    /// entries from the front of the log. A no-op if this subscription never read `topic`.
+    fn retreat(&mut self, topic: &str, count: usize) {
+        if let Some(offset) = self.offsets.get_mut(topic) {
+            *offset -= count;
         }
     }
 }
 
+/// A trie over `/`-split topic-filter levels (MQTT-4.7), supporting `+` (matches exactly one
+/// level) and `#` (matches the remainder, only legal as the final level). Built fresh per
+/// connection per `route` tick from that connection's wildcard filters, then used to find every
+/// filter matching a commitlog topic in one pass instead of checking each filter against each
+/// topic in turn.
+#[derive(Debug, Default)]
+struct TopicTrie {
+    root: TrieNode,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<String, TrieNode>,
+    plus: Option<Box<TrieNode>>,
+    // Filters ending in `#` at this level - matches this level and everything below it.
+    multi: Vec<String>,
+    // Filters with no wildcard levels remaining - matches only this exact depth.
+    exact: Vec<String>,
+}
+
+impl TopicTrie {
+    fn new() -> TopicTrie {
+        TopicTrie::default()
+    }
+
+    fn insert(&mut self, filter: &str) {
+        let levels: Vec<&str> = filter.split('/').collect();
+        let mut node = &mut self.root;
+        for (i, level) in levels.iter().enumerate() {
+            if *level == "#" {
+                node.multi.push(filter.to_owned());
+                return;
+            }
+
+            node = if *level == "+" {
+                &mut **node.plus.get_or_insert_with(|| Box::new(TrieNode::default()))
+            } else {
+                node.children.entry((*level).to_owned()).or_insert_with(TrieNode::default)
+            };
+
+            if i == levels.len() - 1 {
+                node.exact.push(filter.to_owned());
+            }
+        }
+    }
+
+    /// Returns every filter inserted into this trie that matches `topic`.
+    fn matching_filters(&self, topic: &str) -> Vec<String> {
+        let levels: Vec<&str> = topic.split('/').collect();
+        let mut out = Vec::new();
+        TopicTrie::walk(&self.root, &levels, &mut out);
+        out
+    }
+
+    fn walk(node: &TrieNode, levels: &[&str], out: &mut Vec<String>) {
+        out.extend(node.multi.iter().cloned());
+
+        let (level, rest) = match levels.split_first() {
+            Some(pair) => pair,
+            None => {
+                out.extend(node.exact.iter().cloned());
+                return;
+            }
+        };
+
+        if let Some(child) = node.children.get(*level) {
+            TopicTrie::walk(child, rest, out);
+        }
+        if let Some(plus) = &node.plus {
+            TopicTrie::walk(plus, rest, out);
+        }
+    }
+}
+
+/// A `$share/<group>/<filter>` subscription (MQTT-4.8.2): every member receives the same
+/// subscription, but each matching publish is delivered to exactly one member, chosen by
+/// round-robin, instead of being fanned out to all of them.
+#[derive(Debug)]
+struct SharedGroup {
+    qos: QoS,
+    members: Vec<String>,
+    // Index into `members` of the next member in line for round-robin delivery.
+    cursor: usize,
+    // Single shared cursor into `Router::commitlog[filter]` - every group member reads from the
+    // same position, unlike a normal `Subscription` where each member tracks its own.
+    offset: usize,
+}
+
+impl SharedGroup {
+    fn new(qos: QoS) -> SharedGroup {
+        SharedGroup { qos, members: Vec::new(), cursor: 0, offset: 0 }
+    }
+
+    fn add_member(&mut self, id: String) {
+        if !self.members.contains(&id) {
+            self.members.push(id);
+        }
+    }
+
+    fn remove_member(&mut self, id: &str) {
+        self.members.retain(|member| member != id);
+        if self.cursor >= self.members.len() {
+            self.cursor = 0;
+        }
+    }
+
+    fn advance(&mut self, count: usize) {
+        self.offset += count;
+    }
+
+    /// Shifts the shared cursor back by `count` after `compact_commitlog` has drained that many
+    /// entries from the front of the log.
+    fn retreat(&mut self, count: usize) {
+        self.offset -= count;
+    }
+}
+
+/// Splits a `$share/<group>/<filter>` topic filter into its group name and underlying filter
+/// (MQTT-4.8.2). Returns `None` for a normal (non-shared) filter, or a malformed shared one
+/// missing a group name or filter.
+fn parse_shared_filter(filter: &str) -> Option<(String, String)> {
+    let rest = filter.strip_prefix("$share/")?;
+    let mut parts = rest.splitn(2, '/');
+    let group = parts.next()?;
+    let filter = parts.next()?;
+    if group.is_empty() || filter.is_empty() {
+        return None;
+    }
+    Some((group.to_owned(), filter.to_owned()))
+}
+
+/// Tracks how long a connection's `tx` has been refusing sends (`TrySendError::Full`), modeled
+/// on peer-manager joining-node timeouts: a few strikes are tolerated before giving up, but a
+/// consumer stuck full for too long is evicted even if it hasn't hit the strike count yet.
 #[derive(Debug)]
+struct Backpressure {
+    strikes: u32,
+    // Set on the first strike since the last successful send, cleared by `reset`.
+    deadline: Option<Instant>,
+}
+
+impl Backpressure {
+    fn new() -> Backpressure {
+        Backpressure {
+            strikes: 0,
+            deadline: None,
+        }
+    }
+
+    /// Records a successful send, forgiving any strikes accumulated since the last one.
+    fn reset(&mut self) {
+        self.strikes = 0;
+        self.deadline = None;
+    }
+
+    /// Records a `TrySendError::Full`. Returns `true` if the connection should now be evicted.
+    fn strike(&mut self, max_strikes: u32, grace_period: Duration) -> bool {
+        self.strikes += 1;
+        let deadline = *self.deadline.get_or_insert_with(|| Instant::now() + grace_period);
+        self.strikes > max_strikes || Instant::now() >= deadline
+    }
+}
+
+#[derive(Debug)]
+/// Decides whether a client may subscribe to a topic filter, and at what QoS. Called once per
+/// `SubscribeTopic` in an incoming SUBSCRIBE, so embedders can enforce per-client topic ACLs
+/// and QoS caps. `None` rejects the topic (reported to the client as SUBACK failure, 0x80);
+/// `Some(qos)` grants it, possibly at a lower QoS than the client asked for.
+pub trait SubscriptionHandler {
+    fn authorize(&self, client_id: &str, topic: &SubscribeTopic) -> Option<QoS>;
+}
+
+/// Default handler used when the embedder doesn't set one: grants every subscription at the
+/// QoS the client requested.
+struct AllowAll;
+
+impl SubscriptionHandler for AllowAll {
+    fn authorize(&self, _client_id: &str, topic: &SubscribeTopic) -> Option<QoS> {
+        Some(topic.qos)
+    }
+}
+
+/// Controls when a freshly subscribed client receives retained publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainMode {
+    /// Deliver retained publishes on the very next `route` tick, regardless of whether the
+    /// subscriber has caught up on live traffic that predates the subscription. This is the
+    /// historical behavior.
+    Immediate,
+    /// Hold retained publishes back until the subscriber's cursor for that topic has caught
+    /// up to the log position it had at subscribe time, so a retained snapshot never arrives
+    /// ahead of fresher live publishes on the same topic during a reconnect storm.
+    SyncWait,
+}
+
 pub struct Router {
     commitlog: HashMap<String, Vec<Publish>>,
     // handles to all active connections. used to route data
@@ -228,19 +576,216 @@ pub struct Router {
     inactive_connections:   HashMap<String, InactiveConnection>,
     // retained publishes
     retained_publishes:     HashMap<String, Publish>,
+    // `$share/<group>/<filter>` subscriptions, keyed by (group, filter). Delivery fans out
+    // across groups as usual, but within one group only a single member gets each batch.
+    shared_subscriptions:   HashMap<(String, String), SharedGroup>,
+    // Wills staged by `deactivate_and_forward_will`, keyed by client id, awaiting their
+    // `will_delay` deadline in `sweep_due_wills`. Removed without publishing if the client
+    // reconnects before the deadline (MQTT-3.1.3.2.2's Will Delay Interval).
+    pending_wills:          HashMap<String, (Instant, Publish)>,
+    // How long a will sits in `pending_wills` before publishing. `rumq_core::mqtt4::Connect` is
+    // MQTT 3.1.1 and carries no Will Delay Interval property to read per-client, so this
+    // broker-wide default stands in until property support lands here. Defaults to zero, i.e.
+    // publish on the next tick after death.
+    will_delay:             Duration,
     // channel receiver to receive data from all the active_connections.
     // each connection will have a tx handle
     data_rx:                Receiver<(String, RouterMessage)>,
+    // authorizes incoming subscriptions and grants their QoS. defaults to allow-all
+    subscription_handler:   Box<dyn SubscriptionHandler + Send + Sync>,
+    // counter used to mint client ids for `subscribe_internal` connections
+    internal_subscriber_count: usize,
+    // when retained publishes are handed to a freshly subscribed client. defaults to `Immediate`
+    retain_mode:             RetainMode,
+    // strikes a slow consumer may rack up on `TrySendError::Full` before eviction
+    max_strikes:             u32,
+    // how long a consumer may sit full with zero successful sends before eviction, even if it
+    // hasn't hit `max_strikes` yet
+    grace_period:            Duration,
+    // server-enforced ceiling on the keep-alive (in seconds) a client may request in CONNECT.
+    // `None` means accept whatever the client asks for. Defaults to `None`.
+    max_keep_alive:          Option<u16>,
+    // How long a persistent session sits in `inactive_connections` before
+    // `sweep_expired_sessions` permanently drops it. `rumq_core::mqtt4::Connect`/`Disconnect`
+    // are MQTT 3.1.1 and carry no Session Expiry Interval property to read per-client, so this
+    // broker-wide default stands in for both the CONNECT value and any DISCONNECT override
+    // until property support lands here. `None` is MQTT5's "absent" - retained until broker
+    // restart; `Some(Duration::ZERO)` discards the session immediately instead of retaining it.
+    session_expiry:          Option<Duration>,
+    // Disk-backed mirror of `commitlog`, kept in sync by `fill_commitlog` and trimmed by
+    // `enforce_commitlog_retention`. `None` (the default) means `commitlog` is purely in-memory
+    // and nothing survives a restart - see `set_commitlog_dir`.
+    disk_log:                Option<CommitLog>,
 }
 
 impl Router {
-    pub fn new(data_rx: Receiver<(String, RouterMessage)>) -> Self {
+    pub fn new(data_rx: Receiver<(String, RouterMessage)>, max_strikes: u32, grace_period: Duration) -> Self {
         Router {
             commitlog: HashMap::new(),
             active_connections: HashMap::new(),
             inactive_connections: HashMap::new(),
             retained_publishes: HashMap::new(),
+            shared_subscriptions: HashMap::new(),
+            pending_wills: HashMap::new(),
+            will_delay: Duration::from_secs(0),
             data_rx,
+            subscription_handler: Box::new(AllowAll),
+            internal_subscriber_count: 0,
+            retain_mode: RetainMode::Immediate,
+            max_strikes,
+            grace_period,
+            max_keep_alive: None,
+            session_expiry: None,
+            disk_log: None,
+        }
+    }
+
+    /// Backs `commitlog` with a disk-persisted, retention-enforced log rooted at `dir`: segments
+    /// of `max_segment_size` bytes, up to `segments_per_partition` per topic, additionally
+    /// trimmed by `retention`. Replays every partition already on disk into the in-memory
+    /// `commitlog` before returning, so publishes written before a restart are still there to
+    /// deliver. Without calling this, `commitlog` stays purely in-memory (the default) and
+    /// nothing survives a restart.
+    pub fn set_commitlog_dir(
+        &mut self,
+        dir: impl Into<PathBuf>,
+        max_segment_size: usize,
+        segments_per_partition: usize,
+        retention: RetentionPolicy,
+    ) -> Result<(), commitlog::Error> {
+        let mut disk_log = CommitLog::new(dir, max_segment_size, segments_per_partition, retention)?;
+
+        for topic in disk_log.topics() {
+            let mut segment_id = 0;
+            let mut log_offset = 0;
+            let mut recovered = Vec::new();
+
+            while let Some(messages) = disk_log.get(&topic, segment_id, log_offset, usize::MAX) {
+                segment_id = messages.segment_id;
+                log_offset = messages.log_offset + 1;
+                recovered.extend(messages.packets);
+            }
+
+            self.commitlog.insert(topic, recovered);
+        }
+
+        self.disk_log = Some(disk_log);
+        Ok(())
+    }
+
+    /// Swaps in a custom `SubscriptionHandler`, replacing the default allow-all behavior.
+    pub fn set_subscription_handler(&mut self, handler: Box<dyn SubscriptionHandler + Send + Sync>) {
+        self.subscription_handler = handler;
+    }
+
+    /// Sets how retained publishes are delivered to newly subscribed clients. See `RetainMode`.
+    pub fn set_retain_mode(&mut self, retain_mode: RetainMode) {
+        self.retain_mode = retain_mode;
+    }
+
+    /// Caps the keep-alive (seconds) a CONNECT may request. A client asking for more than
+    /// `max_keep_alive` is clamped down to it (MQTT-3.1.2.10's server-override allowance); a
+    /// client asking for less, or for 0 (keep-alive disabled), is left alone.
+    ///
+    /// This is server-side *enforcement* only, not negotiation: the clamped value is used
+    /// locally by `sweep_keep_alives` but is never echoed back in CONNACK's `server_keep_alive`
+    /// (see the NOTE in `handle_connect`), so a client that requested above the cap has no way
+    /// to learn the broker shortened it before `sweep_keep_alives` acts on that shorter value.
+    pub fn set_max_keep_alive(&mut self, max_keep_alive: u16) {
+        self.max_keep_alive = Some(max_keep_alive);
+    }
+
+    /// A requested keep-alive of 0 means "disable the timeout" (MQTT-3.1.2.10) and is always
+    /// honored; anything else above `max_keep_alive` is clamped down to it.
+    fn clamp_keep_alive(max_keep_alive: Option<u16>, requested: u16) -> u16 {
+        match max_keep_alive {
+            Some(max) if requested != 0 && requested > max => max,
+            _ => requested,
+        }
+    }
+
+    /// Sets how long a persistent session is retained in `inactive_connections` after its
+    /// connection closes. `Duration::ZERO` discards sessions on disconnect instead of retaining
+    /// them. See the `session_expiry` field doc for why this is broker-wide rather than
+    /// per-client.
+    pub fn set_session_expiry(&mut self, session_expiry: Duration) {
+        self.session_expiry = Some(session_expiry);
+    }
+
+    /// Sets how long a will sits staged after its connection dies before it's actually
+    /// published. See the `will_delay` field doc for why this is broker-wide rather than
+    /// per-client.
+    pub fn set_will_delay(&mut self, will_delay: Duration) {
+        self.will_delay = will_delay;
+    }
+
+    /// Computes the deadline a session moving into `inactive_connections` should carry, or
+    /// `Err(())` when the configured expiry is zero and the session must be discarded instead of
+    /// retained at all.
+    fn inactive_expiry_deadline(&self) -> Result<Option<Instant>, ()> {
+        match self.session_expiry {
+            None => Ok(None),
+            Some(d) if d == Duration::from_secs(0) => Err(()),
+            Some(d) => Ok(Some(Instant::now() + d)),
+        }
+    }
+
+    /// Permanently drops persistent sessions past their `expires_at` deadline, freeing their
+    /// retained commitlog offsets and subscriptions along with them.
+    fn sweep_expired_sessions(&mut self) {
+        let now = Instant::now();
+        self.inactive_connections.retain(|_, connection| match connection.expires_at {
+            Some(expires_at) => now < expires_at,
+            None => true,
+        });
+    }
+
+    /// Subscribes an in-process Rust callback to broker traffic without a TCP MQTT client.
+    /// `handle` receives matched publishes as `RouterMessage::Publishes` through the normal
+    /// `route` path, the same way a real connection does. Useful for bridging topics into
+    /// application logic - metrics, logging, transformation, forwarding to another bus -
+    /// entirely in-process. Returns the client id the virtual connection was registered
+    /// under, for bookkeeping by the caller.
+    pub fn subscribe_internal(&mut self, filters: Vec<String>, handle: Sender<RouterMessage>) -> String {
+        let id = format!("internal-{}", self.internal_subscriber_count);
+        self.internal_subscriber_count += 1;
+
+        let topics: Vec<SubscribeTopic> = filters
+            .into_iter()
+            .map(|topic_path| SubscribeTopic { topic_path, qos: QoS::AtMostOnce })
+            .collect();
+        let retained_publishes = self.match_retainted_publishes(&topics);
+
+        let mut connection = ActiveConnection::new_internal(handle);
+        connection.add_to_subscriptions(Subscribe { pkid: 0, topics });
+        Router::stage_retained_publishes(self.retain_mode, &self.commitlog, &mut connection, retained_publishes);
+        self.active_connections.insert(id.clone(), connection);
+
+        id
+    }
+
+    /// Hands `retained_publishes` to `connection` according to `retain_mode`. A free function
+    /// (not a `&self` method) so callers can hold it alongside a borrow of a single entry in
+    /// `self.active_connections`.
+    fn stage_retained_publishes(
+        retain_mode: RetainMode,
+        commitlog: &HashMap<String, Vec<Publish>>,
+        connection: &mut ActiveConnection,
+        retained_publishes: Vec<Publish>,
+    ) {
+        match retain_mode {
+            RetainMode::Immediate => connection.retained.extend(retained_publishes),
+            RetainMode::SyncWait => {
+                for publish in retained_publishes {
+                    let snapshot_len = commitlog.get(&publish.topic_name).map_or(0, |log| log.len());
+                    connection
+                        .pending_retained
+                        .entry(publish.topic_name.clone())
+                        .or_insert_with(|| (snapshot_len, Vec::new()))
+                        .1
+                        .push(publish);
+                }
+            }
         }
     }
 
@@ -265,6 +810,10 @@ impl Router {
                     self.fill_and_track(id, message);
                 }
                 _ = interval.next() => {
+                    self.sweep_keep_alives();
+                    self.sweep_expired_sessions();
+                    self.sweep_due_wills();
+                    self.enforce_commitlog_retention();
                     self.route()
                 }
             }
@@ -300,17 +849,69 @@ impl Router {
         match message {
             RouterMessage::Packet(packet) => {
                 match packet {
-                    Packet::Publish(publish) => {
+                    Packet::Publish(mut publish) => {
+                        // `rumq_core::mqtt4::Publish` is MQTT 3.1.1 and carries no Topic Alias
+                        // property, so every publish reaching this point resolves as a pass-
+                        // through today (`alias: None`) - wired in now so that moving the broker
+                        // onto a v5 PUBLISH type only needs to plumb the decoded alias through.
+                        if let Some(connection) = self.active_connections.get_mut(&id) {
+                            if let Ok(resolved) = connection.resolve_topic_alias(None, &publish.topic_name) {
+                                publish.topic_name = resolved;
+                            }
+                        }
                         self.fill_commitlog(publish.clone());
                     }
-                    Packet::Subscribe(subscribe) => {
+                    Packet::Subscribe(mut subscribe) => {
+                        let pkid = subscribe.pkid;
+                        let mut return_codes = Vec::with_capacity(subscribe.topics.len());
+                        let mut authorized_topics = Vec::with_capacity(subscribe.topics.len());
+                        let mut shared_topics = Vec::new();
+                        for mut topic in subscribe.topics.drain(..) {
+                            match self.subscription_handler.authorize(&id, &topic) {
+                                Some(qos) => {
+                                    topic.qos = qos;
+                                    return_codes.push(qos as u8);
+                                    match parse_shared_filter(&topic.topic_path) {
+                                        Some((group, filter)) => shared_topics.push((group, filter, qos)),
+                                        None => authorized_topics.push(topic),
+                                    }
+                                }
+                                None => return_codes.push(0x80),
+                            }
+                        }
+                        subscribe.topics = authorized_topics;
+
+                        // A `$share/<group>/<filter>` topic doesn't become a normal
+                        // `Subscription` on the connection - it registers the connection as a
+                        // round-robin member of the group instead, so `route` delivers each
+                        // matching publish to exactly one member.
+                        for (group, filter, qos) in shared_topics {
+                            self.shared_subscriptions
+                                .entry((group, filter))
+                                .or_insert_with(|| SharedGroup::new(qos))
+                                .add_member(id.clone());
+                        }
+
                         let retained_publishes = self.match_retainted_publishes(&subscribe.topics);
+                        let retain_mode = self.retain_mode;
                         if let Some(connection) = self.active_connections.get_mut(&id) {
-                            connection.add_to_subscriptions(subscribe, retained_publishes);
+                            connection.add_to_subscriptions(subscribe);
+                            Router::stage_retained_publishes(retain_mode, &self.commitlog, connection, retained_publishes);
+                            let suback = SubAck::new(pkid, return_codes);
+                            let _ = connection.tx.try_send(RouterMessage::Packet(Packet::SubAck(suback)));
                         }
                     }
-                    Packet::Unsubscribe(unsubscribe) => if let Some(connection) = self.active_connections.get_mut(&id) {
-                        connection.remove_from_subscriptions(unsubscribe);
+                    Packet::Unsubscribe(unsubscribe) => {
+                        for topic in unsubscribe.topics.iter() {
+                            if let Some((group, filter)) = parse_shared_filter(topic) {
+                                if let Some(shared_group) = self.shared_subscriptions.get_mut(&(group, filter)) {
+                                    shared_group.remove_member(&id);
+                                }
+                            }
+                        }
+                        if let Some(connection) = self.active_connections.get_mut(&id) {
+                            connection.remove_from_subscriptions(unsubscribe);
+                        }
                     }
                     Packet::Disconnect => self.deactivate(id),
                     _ => return
@@ -333,6 +934,12 @@ impl Router {
             }
         }
 
+        if let Some(disk_log) = self.disk_log.as_mut() {
+            if let Err(e) = disk_log.fill(publish.clone()) {
+                error!("Failed to persist publish to disk. Topic = {}. Error = {:?}", publish.topic_name, e);
+            }
+        }
+
         if let Some(publishes) = self.commitlog.get_mut(&publish.topic_name) {
             publishes.push(publish)
         } else {
@@ -344,29 +951,54 @@ impl Router {
         }
     }
 
+    /// Trims the disk-backed commitlog down to its configured retention limits, if one is
+    /// configured via `set_commitlog_dir`. No-op otherwise.
+    fn enforce_commitlog_retention(&mut self) {
+        if let Some(disk_log) = self.disk_log.as_mut() {
+            disk_log.enforce_retention();
+        }
+    }
+
     fn handle_connect(&mut self, connect: Connect, connection_handle: Sender<RouterMessage>) -> Result<Option<RouterMessage>, Error> {
         let id = connect.client_id;
         let clean_session = connect.clean_session;
         let will = connect.last_will;
 
+        // Reconnecting before a staged will's `will_delay` elapses cancels it outright
+        // (MQTT-3.1.3.2.2) - the client is demonstrably alive again.
+        self.pending_wills.remove(&id);
+
+        let keep_alive = Self::clamp_keep_alive(self.max_keep_alive, connect.keep_alive);
+        let keep_alive_duration = if keep_alive == 0 { None } else { Some(Duration::from_secs(keep_alive as u64)) };
+
         info!("Connect. Id = {:?}", id);
+        // NOTE: a well-behaved broker would also echo its own Receive Maximum, and this clamped
+        // keep-alive (now available via `ActiveConnection::server_keep_alive`), back to the
+        // client here via CONNACK's `ConnackProperties::receive_maximum` / `server_keep_alive`
+        // properties, so the client adopts our limits instead of silently being disconnected by
+        // `sweep_keep_alives` for honoring the value it asked for. Actually sending that CONNACK
+        // needs `rumq_core::mqtt4::Packet`'s ConnAck variant, and `rumq_core`'s source isn't
+        // present in this tree to see its shape - guessing at it here risks shipping a broker
+        // that claims to negotiate v5 properties it can't actually encode correctly. Split out
+        // as its own follow-up once `rumq_core` (or a v5 CONNACK path) is available, rather than
+        // built blind against this request.
         let reply = if clean_session {
             self.inactive_connections.remove(&id);
 
             let state = MqttState::new(clean_session, will);
-            self.active_connections.insert(id.clone(), ActiveConnection::new(connection_handle, state));
+            self.active_connections.insert(id.clone(), ActiveConnection::new(connection_handle, state, keep_alive_duration));
             Some(RouterMessage::Pending(VecDeque::new()))
         } else {
             if let Some(connection) = self.inactive_connections.remove(&id) {
                 /*
                    let pending = connection.state.outgoing_publishes.clone();
-                   self.active_connections.insert(id.clone(), ActiveConnection::new(connection_handle, connection.state));
+                   self.active_connections.insert(id.clone(), ActiveConnection::new(connection_handle, connection.state, keep_alive_duration));
                    Some(RouterMessage::Pending(pending))
                    */
                 None
             } else {
                 let state = MqttState::new(clean_session, will);
-                self.active_connections.insert(id.clone(), ActiveConnection::new(connection_handle, state));
+                self.active_connections.insert(id.clone(), ActiveConnection::new(connection_handle, state, keep_alive_duration));
                 Some(RouterMessage::Pending(VecDeque::new()))
             }
         };
@@ -376,6 +1008,8 @@ impl Router {
     }
 
     fn route(&mut self) {
+        let max_strikes = self.max_strikes;
+        let grace_period = self.grace_period;
         let active_connections = &mut self.active_connections;
         let mut closed_connections = Vec::new();
         let graveyard = &mut closed_connections;
@@ -384,39 +1018,331 @@ impl Router {
             if connection.retained.len() > 0 {
                 let mut publishes = connection.retained.split_off(0);
                 // TODO: Use correct qos
-                connection.state.handle_outgoing_publishes(QoS::AtLeastOnce, &mut publishes);
-                let _ = connection.tx.try_send(RouterMessage::Publishes(publishes));
+                if let Some(state) = connection.state.as_mut() {
+                    state.handle_outgoing_publishes(QoS::AtLeastOnce, &mut publishes);
+                }
+                // Stop sending once this connection's Receive Maximum credit is exhausted;
+                // whatever doesn't fit stays staged in `retained` for a later tick, once
+                // incoming PUBACK/PUBCOMP replenish credit.
+                let (publishes, remainder) = connection.apply_send_credit(publishes);
+                connection.retained.extend(remainder);
+                if publishes.is_empty() {
+                    continue;
+                }
+                match connection.tx.try_send(RouterMessage::Publishes(publishes)) {
+                    Ok(_) => connection.backpressure.reset(),
+                    Err(TrySendError::Full(_)) => {
+                        error!("Routing to a slow connection. Id = {:?}", id);
+                        if connection.backpressure.strike(max_strikes, grace_period) {
+                            error!("Slow connection exceeded backpressure grace. Evicting. Id = {:?}", id);
+                            graveyard.push(id.clone());
+                        }
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        error!("Routing to a closed connection. Id = {:?}", id);
+                        graveyard.push(id.clone());
+                    }
+                }
             }
 
-            let concrete_subscriptions = &mut connection.concrete_subscriptions;
-            let commitlog = &self.commitlog;
-            for (filter, subscription) in concrete_subscriptions.iter_mut() {
-                let qos = subscription.qos;
-                if let Some(publishes) = commitlog.get(filter) {
-                    let mut publishes = publishes.clone();
-                    connection.state.handle_outgoing_publishes(qos, &mut publishes);
-                    match connection.tx.try_send(RouterMessage::Publishes(publishes)) {
-                        Ok(_) => continue,
+            {
+                let concrete_subscriptions = &mut connection.concrete_subscriptions;
+                let commitlog = &self.commitlog;
+                for (filter, subscription) in concrete_subscriptions.iter_mut() {
+                    let qos = subscription.qos;
+                    let offset = subscription.offset(filter);
+                    let publishes = match commitlog.get(filter) {
+                        Some(publishes) if offset < publishes.len() => publishes,
+                        _ => continue,
+                    };
+
+                    let mut unread: Vec<Publish> = publishes[offset..].to_vec();
+                    if let Some(state) = connection.state.as_mut() {
+                        state.handle_outgoing_publishes(qos, &mut unread);
+                    }
+                    // Stop sending - and advancing the cursor - once send credit runs out; the
+                    // remainder stays unread in the commitlog and is retried next tick.
+                    let (unread, _) = connection.apply_send_credit(unread);
+                    let unread_count = unread.len();
+                    if unread.is_empty() {
+                        continue;
+                    }
+                    match connection.tx.try_send(RouterMessage::Publishes(unread)) {
+                        // Only advance the cursor once the connection has actually accepted
+                        // the batch, so a `Full`/`Closed` send leaves these publishes to be
+                        // redelivered.
+                        Ok(_) => {
+                            subscription.advance(filter, unread_count);
+                            connection.backpressure.reset();
+                            continue;
+                        }
                         Err(TrySendError::Full(_)) => {
-                            error!("Routint to a closed connection. Id = {:?}", id);
-                            graveyard.push(id.clone());
+                            error!("Routing to a slow connection. Id = {:?}", id);
+                            if connection.backpressure.strike(max_strikes, grace_period) {
+                                error!("Slow connection exceeded backpressure grace. Evicting. Id = {:?}", id);
+                                graveyard.push(id.clone());
+                            }
                             continue;
                         }
                         Err(TrySendError::Closed(_)) => {
-                            error!("Routint to a closed connection. Id = {:?}", id);
+                            error!("Routing to a closed connection. Id = {:?}", id);
                             graveyard.push(id.clone());
                             continue;
                         }
                     }
                 }
             }
+
+            // Wildcard subscriptions don't have a single matching commitlog entry the way
+            // concrete ones do. Build a trie from this connection's wildcard filters once, then
+            // walk every commitlog topic through it a single time to find every filter it
+            // matches, instead of checking each filter against each topic in turn.
+            let mut filter_trie = TopicTrie::new();
+            for filter in connection.wild_subscriptions.keys() {
+                filter_trie.insert(filter);
+            }
+
+            let commitlog = &self.commitlog;
+            for (topic, publishes) in commitlog.iter() {
+                let filters = filter_trie.matching_filters(topic);
+                if filters.is_empty() {
+                    continue;
+                }
+
+                // A topic can match more than one of this connection's wildcard filters at once
+                // (e.g. `a/+/c` and `a/b/+` both matching `a/b/c`, with neither subsuming the
+                // other, so `fix_overlapping_subscriptions` never merged them). Read from
+                // whichever matching filter is furthest behind and deliver once - not once per
+                // matching filter - so a given (client, log offset) is never sent twice; then
+                // bring every matching filter's cursor up to date.
+                let mut offset = None;
+                let mut qos = QoS::AtMostOnce;
+                for filter in &filters {
+                    if let Some(subscription) = connection.wild_subscriptions.get(filter) {
+                        let filter_offset = subscription.offset(topic);
+                        offset = Some(offset.map_or(filter_offset, |o: usize| o.min(filter_offset)));
+                        if subscription.qos > qos {
+                            qos = subscription.qos;
+                        }
+                    }
+                }
+                let offset = match offset {
+                    Some(offset) if offset < publishes.len() => offset,
+                    _ => continue,
+                };
+
+                let mut unread: Vec<Publish> = publishes[offset..].to_vec();
+                if let Some(state) = connection.state.as_mut() {
+                    state.handle_outgoing_publishes(qos, &mut unread);
+                }
+                // Stop sending - and advancing the cursor - once send credit runs out; the
+                // remainder stays unread in the commitlog and is retried next tick.
+                let (unread, _) = connection.apply_send_credit(unread);
+                if unread.is_empty() {
+                    continue;
+                }
+                let caught_up_to = offset + unread.len();
+
+                match connection.tx.try_send(RouterMessage::Publishes(unread)) {
+                    Ok(_) => {
+                        for filter in &filters {
+                            if let Some(subscription) = connection.wild_subscriptions.get_mut(filter) {
+                                // Credit clipping can shrink `unread` below what a filter that
+                                // started further ahead than the global minimum had already read,
+                                // so `caught_up_to` isn't necessarily past every matching filter's
+                                // offset - only advance filters it's actually ahead of.
+                                let current = subscription.offset(topic);
+                                if caught_up_to > current {
+                                    subscription.advance(topic, caught_up_to - current);
+                                }
+                            }
+                        }
+                        connection.backpressure.reset();
+                    }
+                    Err(TrySendError::Full(_)) => {
+                        error!("Routing to a slow connection. Id = {:?}", id);
+                        if connection.backpressure.strike(max_strikes, grace_period) {
+                            error!("Slow connection exceeded backpressure grace. Evicting. Id = {:?}", id);
+                            graveyard.push(id.clone());
+                        }
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        error!("Routing to a closed connection. Id = {:?}", id);
+                        graveyard.push(id.clone());
+                    }
+                }
+            }
+
+            // `RetainMode::SyncWait`: release retained publishes staged per-topic once this
+            // connection's cursor has caught up to the log position it had at subscribe time,
+            // so they queue up behind (never ahead of) live traffic that predates the subscribe.
+            if !connection.pending_retained.is_empty() {
+                let ready_topics: Vec<String> = connection
+                    .pending_retained
+                    .iter()
+                    .filter(|(topic, (snapshot_len, _))| {
+                        let offset = connection
+                            .concrete_subscriptions
+                            .get(*topic)
+                            .map(|subscription| subscription.offset(topic))
+                            .or_else(|| {
+                                connection
+                                    .wild_subscriptions
+                                    .iter()
+                                    .find(|(filter, _)| matches(topic, filter))
+                                    .map(|(_, subscription)| subscription.offset(topic))
+                            })
+                            .unwrap_or(0);
+
+                        offset >= *snapshot_len
+                    })
+                    .map(|(topic, _)| topic.clone())
+                    .collect();
+
+                for topic in ready_topics {
+                    if let Some((_, publishes)) = connection.pending_retained.remove(&topic) {
+                        connection.retained.extend(publishes);
+                    }
+                }
+            }
         }
 
-        mem::replace(&mut self.commitlog, HashMap::new());
+        // Deliver shared subscriptions: the group's single offset advances once per matching
+        // batch, and the batch goes to exactly one member - starting at the round-robin cursor,
+        // falling through to the next member if `try_send` comes back full or closed.
+        for ((_, filter), group) in self.shared_subscriptions.iter_mut() {
+            if group.members.is_empty() {
+                continue;
+            }
+
+            let publishes = match self.commitlog.get(filter) {
+                Some(publishes) if group.offset < publishes.len() => publishes,
+                _ => continue,
+            };
+            let unread: Vec<Publish> = publishes[group.offset..].to_vec();
+
+            let member_count = group.members.len();
+            for attempt in 0..member_count {
+                let member_index = (group.cursor + attempt) % member_count;
+                let member_id = group.members[member_index].clone();
+                let connection = match active_connections.get_mut(&member_id) {
+                    Some(connection) => connection,
+                    None => continue,
+                };
+
+                let mut unread = unread.clone();
+                if let Some(state) = connection.state.as_mut() {
+                    state.handle_outgoing_publishes(group.qos, &mut unread);
+                }
+                let (unread, _) = connection.apply_send_credit(unread);
+                if unread.is_empty() {
+                    continue;
+                }
+                let sent_count = unread.len();
+
+                match connection.tx.try_send(RouterMessage::Publishes(unread)) {
+                    Ok(_) => {
+                        connection.backpressure.reset();
+                        group.cursor = (member_index + 1) % member_count;
+                        group.advance(sent_count);
+                        break;
+                    }
+                    Err(TrySendError::Full(_)) => {
+                        error!("Routing to a slow connection. Id = {:?}", member_id);
+                        continue;
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        error!("Routing to a closed connection. Id = {:?}", member_id);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // Reclaim memory for publishes every subscribed connection has already consumed,
+        // without ever dropping data a lagging subscriber hasn't read yet.
+        self.compact_commitlog();
 
         for id in closed_connections.into_iter() {
             if let Some(connection) = active_connections.remove(&id) {
-                self.inactive_connections.insert(id.to_owned(), InactiveConnection::new(connection.state));
+                for group in self.shared_subscriptions.values_mut() {
+                    group.remove_member(&id);
+                }
+                if let Ok(expires_at) = self.inactive_expiry_deadline() {
+                    self.inactive_connections.insert(id.to_owned(), InactiveConnection::from_active(connection, expires_at));
+                }
+            }
+        }
+    }
+
+    /// Truncates each topic's commit log up to the lowest read offset (the "low watermark")
+    /// among connections currently subscribed to it, and shifts those connections' cursors
+    /// back by the same amount. A topic with no active subscriber is left untouched, since
+    /// there's no cursor yet to bound the truncation by.
+    fn compact_commitlog(&mut self) {
+        let mut low_watermarks: HashMap<String, usize> = HashMap::new();
+        for connection in self.active_connections.values() {
+            for (filter, subscription) in connection.concrete_subscriptions.iter() {
+                low_watermarks
+                    .entry(filter.clone())
+                    .and_modify(|offset| *offset = (*offset).min(subscription.offset(filter)))
+                    .or_insert_with(|| subscription.offset(filter));
+            }
+
+            // A wildcard subscription's cursors are keyed by topic, not by its filter, so fold
+            // its per-topic offsets into the same watermark map the concrete subscriptions use.
+            for (filter, subscription) in connection.wild_subscriptions.iter() {
+                for topic in self.commitlog.keys() {
+                    if !matches(topic, filter) {
+                        continue;
+                    }
+
+                    low_watermarks
+                        .entry(topic.clone())
+                        .and_modify(|offset| *offset = (*offset).min(subscription.offset(topic)))
+                        .or_insert_with(|| subscription.offset(topic));
+                }
+            }
+        }
+
+        for ((_, filter), group) in self.shared_subscriptions.iter() {
+            low_watermarks
+                .entry(filter.clone())
+                .and_modify(|offset| *offset = (*offset).min(group.offset))
+                .or_insert_with(|| group.offset);
+        }
+
+        for (topic, watermark) in low_watermarks.iter() {
+            if *watermark == 0 {
+                continue;
+            }
+
+            let drain_count = match self.commitlog.get_mut(topic) {
+                Some(publishes) => {
+                    let drain_count = (*watermark).min(publishes.len());
+                    if drain_count > 0 {
+                        publishes.drain(0..drain_count);
+                    }
+                    drain_count
+                }
+                None => continue,
+            };
+
+            if drain_count > 0 {
+                for connection in self.active_connections.values_mut() {
+                    if let Some(subscription) = connection.concrete_subscriptions.get_mut(topic) {
+                        subscription.retreat(topic, drain_count);
+                    }
+                    for subscription in connection.wild_subscriptions.values_mut() {
+                        subscription.retreat(topic, drain_count);
+                    }
+                }
+
+                for ((_, filter), group) in self.shared_subscriptions.iter_mut() {
+                    if filter == topic {
+                        group.retreat(drain_count);
+                    }
+                }
             }
         }
     }
@@ -424,9 +1350,17 @@ impl Router {
     fn deactivate(&mut self, id: String) {
         info!("Deactivating client due to disconnect packet. Id = {}", id);
 
+        for group in self.shared_subscriptions.values_mut() {
+            group.remove_member(&id);
+        }
+
         if let Some(connection) = self.active_connections.remove(&id) {
-            if !connection.state.clean_session {
-                self.inactive_connections.insert(id, InactiveConnection::new(connection.state));
+            // An in-process subscriber (`connection.state == None`) has no session to persist.
+            let persistent_session = connection.state.as_ref().map_or(false, |state| !state.clean_session);
+            if persistent_session {
+                if let Ok(expires_at) = self.inactive_expiry_deadline() {
+                    self.inactive_connections.insert(id, InactiveConnection::from_active(connection, expires_at));
+                }
             }
         }
     }
@@ -434,18 +1368,68 @@ impl Router {
     fn deactivate_and_forward_will(&mut self, id: String) {
         info!("Deactivating client due to connection death. Id = {}", id);
 
+        for group in self.shared_subscriptions.values_mut() {
+            group.remove_member(&id);
+        }
+
         if let Some(mut connection) = self.active_connections.remove(&id) {
-            if let Some(mut will) = connection.state.will.take() {
+            // In-process subscribers have no `MqttState`, and so no will to forward.
+            if let Some(mut will) = connection.state.as_mut().and_then(|state| state.will.take()) {
                 let topic = mem::replace(&mut will.topic, "".to_owned());
                 let message = mem::replace(&mut will.message, "".to_owned());
                 let qos = will.qos;
 
                 let publish = publish(topic, qos, message);
-                self.fill_commitlog(publish);
+                // Stage rather than publish immediately: `sweep_due_wills` fires it once
+                // `will_delay` elapses, and `handle_connect` cancels it outright if this
+                // client reconnects before then (MQTT-3.1.3.2.2).
+                let deadline = Instant::now() + self.will_delay;
+                self.pending_wills.insert(id.clone(), (deadline, publish));
+            }
+
+            let persistent_session = connection.state.as_ref().map_or(false, |state| !state.clean_session);
+            if persistent_session {
+                if let Ok(expires_at) = self.inactive_expiry_deadline() {
+                    self.inactive_connections.insert(id.clone(), InactiveConnection::from_active(connection, expires_at));
+                }
             }
+        }
+    }
+
+    /// Evicts connections that have gone silent for more than 1.5x their negotiated keep-alive
+    /// (MQTT-3.1.2.10's "one and a half times" grace window), running the same will path a
+    /// detected connection death does.
+    fn sweep_keep_alives(&mut self) {
+        let now = Instant::now();
+        let dead: Vec<String> = self
+            .active_connections
+            .iter()
+            .filter_map(|(id, connection)| match connection.keep_alive {
+                Some(keep_alive) if now.duration_since(connection.last_activity) > keep_alive * 3 / 2 => Some(id.clone()),
+                _ => None,
+            })
+            .collect();
+
+        for id in dead {
+            info!("Keep-alive timeout. Id = {}", id);
+            self.deactivate_and_forward_will(id);
+        }
+    }
 
-            if !connection.state.clean_session {
-                self.inactive_connections.insert(id.clone(), InactiveConnection::new(connection.state));
+    /// Publishes every staged will whose `will_delay` deadline has passed, routing it through
+    /// `fill_commitlog` like any other message.
+    fn sweep_due_wills(&mut self) {
+        let now = Instant::now();
+        let due: Vec<String> = self
+            .pending_wills
+            .iter()
+            .filter(|(_, (deadline, _))| now >= *deadline)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in due {
+            if let Some((_, publish)) = self.pending_wills.remove(&id) {
+                self.fill_commitlog(publish);
             }
         }
     }
@@ -477,10 +1461,26 @@ impl Router {
         publishes
     }
 
-    /// Saves state and sends network reply back to the connection
+    /// Saves state and sends network reply back to the connection. In-process subscribers
+    /// have no `MqttState` and never send packets in, so there's nothing to do for them here.
     fn handle_incoming_packet(&mut self, id: &str, packet: Packet) -> Result<Option<RouterMessage>, Error> {
         if let Some(connection) = self.active_connections.get_mut(id) {
-            let reply = match connection.state.handle_incoming_mqtt_packet(packet) {
+            // Any packet - including a bare PINGREQ - proves the client is still alive, so the
+            // keep-alive sweep in `sweep_keep_alives` should leave it alone for another round.
+            connection.last_activity = Instant::now();
+
+            // A PUBACK (QoS 1) or PUBCOMP (QoS 2) means the client has finished one in-flight
+            // publish, so it can afford one more - replenish the Receive Maximum credit spent
+            // in `route` and give that connection another chance to drain its backlog.
+            if let Packet::PubAck(_) | Packet::PubComp(_) = &packet {
+                connection.send_credit = connection.send_credit.saturating_add(1);
+            }
+
+            let state = match connection.state.as_mut() {
+                Some(state) => state,
+                None => return Ok(None),
+            };
+            let reply = match state.handle_incoming_mqtt_packet(packet) {
                 Ok(Some(reply)) => reply,
                 Ok(None) => return Ok(None),
                 Err(state::Error::Unsolicited(packet)) => {
@@ -491,7 +1491,7 @@ impl Router {
                 }
                 Err(e) => {
                     error!("State error = {:?}. Id = {}", e, id);
-                    self.active_connections.remove(id);
+                    self.deactivate_and_forward_will(id.to_owned());
                     return Err::<_, Error>(e.into())
                 }
             };
@@ -503,21 +1503,36 @@ impl Router {
     }
 
     fn forward(&mut self, id: &str, message: RouterMessage) {
-        if let Some(connection) = self.active_connections.get_mut(id) {
-            // slow connections should be moved to inactive connections. This drops tx handle of the
-            // connection leading to connection disconnection
-            if let Err(e) = connection.tx.try_send(message) {
-                match e {
-                    TrySendError::Full(_m) => {
-                        error!("Slow connection during forward. Dropping handle and moving id to inactive list. Id = {}", id);
-                        if let Some(connection) = self.active_connections.remove(id) {
-                            self.inactive_connections.insert(id.to_owned(), InactiveConnection::new(connection.state));
-                        }
-                    }
-                    TrySendError::Closed(_m) => {
-                        error!("Closed connection. Forward failed");
-                        self.active_connections.remove(id);
-                    }
+        let max_strikes = self.max_strikes;
+        let grace_period = self.grace_period;
+
+        let evict = if let Some(connection) = self.active_connections.get_mut(id) {
+            match connection.tx.try_send(message) {
+                Ok(_) => {
+                    connection.backpressure.reset();
+                    false
+                }
+                // a few strikes (or a short burst of full channels) are tolerated before the
+                // connection is given up on, so a temporarily full consumer keeps its cursors
+                // and resumes cleanly instead of being kicked on the first hiccup.
+                Err(TrySendError::Full(_)) => {
+                    error!("Slow connection during forward. Id = {}", id);
+                    connection.backpressure.strike(max_strikes, grace_period)
+                }
+                Err(TrySendError::Closed(_)) => {
+                    error!("Closed connection. Forward failed. Id = {}", id);
+                    true
+                }
+            }
+        } else {
+            false
+        };
+
+        if evict {
+            error!("Moving id to inactive list. Id = {}", id);
+            if let Some(connection) = self.active_connections.remove(id) {
+                if let Ok(expires_at) = self.inactive_expiry_deadline() {
+                    self.inactive_connections.insert(id.to_owned(), InactiveConnection::from_active(connection, expires_at));
                 }
             }
         }
@@ -529,6 +1544,8 @@ impl Router {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+
     #[test]
     fn persistent_disconnected_and_dead_connections_are_moved_to_inactive_state() {}
 
@@ -555,4 +1572,455 @@ mod test {
 
     #[test]
     fn router_saves_offline_messages_of_a_persistent_dead_connection() {}
-} 
+
+    #[test]
+    fn send_credit_throttles_delivery_once_receive_maximum_is_exhausted() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        connection.send_credit = 2;
+
+        let publishes = vec![
+            publish("a".to_owned(), QoS::AtLeastOnce, "1".to_owned()),
+            publish("a".to_owned(), QoS::AtLeastOnce, "2".to_owned()),
+            publish("a".to_owned(), QoS::AtLeastOnce, "3".to_owned()),
+        ];
+
+        let (sent, remainder) = connection.apply_send_credit(publishes);
+
+        assert_eq!(sent.len(), 2);
+        assert_eq!(remainder.len(), 1);
+        assert_eq!(connection.send_credit, 0);
+    }
+
+    #[test]
+    fn resolve_topic_alias_passes_through_a_publish_with_no_alias() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+
+        assert_eq!(connection.resolve_topic_alias(None, "a/b").unwrap(), "a/b");
+    }
+
+    #[test]
+    fn resolve_topic_alias_records_then_looks_up_an_alias_once_negotiated() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        connection.topic_aliases = TopicAliasMap::new(10);
+
+        assert_eq!(connection.resolve_topic_alias(Some(1), "a/b").unwrap(), "a/b");
+        assert_eq!(connection.resolve_topic_alias(Some(1), "").unwrap(), "a/b");
+    }
+
+    #[test]
+    fn resolve_topic_alias_rejects_an_alias_beyond_the_negotiated_maximum() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+
+        match connection.resolve_topic_alias(Some(1), "a/b") {
+            Err(Error::TopicAlias(mqtt5bytes::Error::InvalidTopicAlias(1))) => {}
+            other => panic!("Expected InvalidTopicAlias(1), got = {:?}", other),
+        }
+    }
+
+    #[test]
+    fn puback_and_pubcomp_replenish_send_credit_and_resume_delivery() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        connection.send_credit = 1;
+
+        let publishes = vec![
+            publish("a".to_owned(), QoS::AtLeastOnce, "1".to_owned()),
+            publish("a".to_owned(), QoS::AtLeastOnce, "2".to_owned()),
+        ];
+        let (sent, remainder) = connection.apply_send_credit(publishes);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(remainder.len(), 1);
+        assert_eq!(connection.send_credit, 0);
+
+        // A PUBACK/PUBCOMP replenishes credit by exactly this operation in
+        // `Router::handle_incoming_packet` - mirrored directly here since
+        // `rumq_core::mqtt4::Packet::PubAck`'s inner type lives outside this tree and can't be
+        // constructed in this snapshot.
+        connection.send_credit = connection.send_credit.saturating_add(1);
+
+        let (sent_again, remainder_again) = connection.apply_send_credit(remainder);
+        assert_eq!(sent_again.len(), 1);
+        assert!(remainder_again.is_empty());
+
+        connection.send_credit = u16::MAX;
+        connection.send_credit = connection.send_credit.saturating_add(1);
+        assert_eq!(connection.send_credit, u16::MAX);
+    }
+
+    #[test]
+    fn connections_silent_beyond_one_and_a_half_times_keep_alive_are_evicted() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut dead = ActiveConnection::new_internal(tx);
+        dead.keep_alive = Some(Duration::from_secs(10));
+        dead.last_activity = Instant::now() - Duration::from_secs(16); // > 1.5 * 10s
+        router.active_connections.insert("dead".to_owned(), dead);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut alive = ActiveConnection::new_internal(tx);
+        alive.keep_alive = Some(Duration::from_secs(10));
+        alive.last_activity = Instant::now();
+        router.active_connections.insert("alive".to_owned(), alive);
+
+        router.sweep_keep_alives();
+
+        assert!(!router.active_connections.contains_key("dead"));
+        assert!(router.active_connections.contains_key("alive"));
+    }
+
+    #[test]
+    fn any_incoming_packet_including_pingreq_refreshes_last_activity() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        connection.last_activity = Instant::now() - Duration::from_secs(60);
+        router.active_connections.insert("c1".to_owned(), connection);
+
+        // `Packet::Disconnect` is a unit variant we can construct without depending on
+        // `rumq_core` internals - any packet refreshes activity before it's dispatched further.
+        router.handle_incoming_packet("c1", Packet::Disconnect).unwrap();
+
+        let last_activity = router.active_connections["c1"].last_activity;
+        assert!(last_activity.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn keep_alive_requested_above_the_configured_maximum_is_clamped_down() {
+        assert_eq!(Router::clamp_keep_alive(Some(30), 60), 30);
+        assert_eq!(Router::clamp_keep_alive(Some(30), 20), 20);
+        assert_eq!(Router::clamp_keep_alive(None, 60), 60);
+    }
+
+    #[test]
+    fn a_requested_keep_alive_of_zero_disables_the_timeout_even_with_a_configured_maximum() {
+        assert_eq!(Router::clamp_keep_alive(Some(30), 0), 0);
+    }
+
+    #[test]
+    fn server_keep_alive_echoes_back_the_clamped_seconds_value() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        connection.keep_alive = Some(Duration::from_secs(30));
+
+        assert_eq!(connection.server_keep_alive(), Some(30));
+    }
+
+    #[test]
+    fn server_keep_alive_is_none_when_the_timeout_is_disabled() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let connection = ActiveConnection::new_internal(tx);
+
+        assert_eq!(connection.server_keep_alive(), None);
+    }
+
+    #[test]
+    fn persistent_sessions_past_their_expiry_deadline_are_dropped_from_inactive_connections() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        router.inactive_connections.insert(
+            "expired".to_owned(),
+            InactiveConnection { state: None, concrete_subscriptions: HashMap::new(), expires_at: Some(Instant::now() - Duration::from_secs(1)) },
+        );
+        router.inactive_connections.insert(
+            "not_yet".to_owned(),
+            InactiveConnection { state: None, concrete_subscriptions: HashMap::new(), expires_at: Some(Instant::now() + Duration::from_secs(60)) },
+        );
+
+        router.sweep_expired_sessions();
+
+        assert!(!router.inactive_connections.contains_key("expired"));
+        assert!(router.inactive_connections.contains_key("not_yet"));
+    }
+
+    #[test]
+    fn a_zero_session_expiry_discards_the_session_instead_of_retaining_it_as_inactive() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+        router.set_session_expiry(Duration::from_secs(0));
+
+        assert_eq!(router.inactive_expiry_deadline(), Err(()));
+    }
+
+    #[test]
+    fn an_absent_session_expiry_retains_the_inactive_session_until_broker_restart() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        assert_eq!(router.inactive_expiry_deadline(), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn shared_subscriptions_deliver_each_publish_to_exactly_one_group_member() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        router.commitlog.insert("a/b".to_owned(), vec![publish("a/b".to_owned(), QoS::AtMostOnce, "hi".to_owned())]);
+
+        let (tx1, mut rx1) = tokio::sync::mpsc::channel(8);
+        let (tx2, mut rx2) = tokio::sync::mpsc::channel(8);
+        router.active_connections.insert("m1".to_owned(), ActiveConnection::new_internal(tx1));
+        router.active_connections.insert("m2".to_owned(), ActiveConnection::new_internal(tx2));
+
+        let mut group = SharedGroup::new(QoS::AtMostOnce);
+        group.add_member("m1".to_owned());
+        group.add_member("m2".to_owned());
+        router.shared_subscriptions.insert(("g".to_owned(), "a/b".to_owned()), group);
+
+        router.route();
+
+        match rx1.recv().await.unwrap() {
+            RouterMessage::Publishes(publishes) => assert_eq!(publishes.len(), 1),
+            other => panic!("expected exactly one batch of publishes, got {:?}", other),
+        }
+
+        // The publish went to exactly one group member - the other must get nothing for it.
+        let nothing_for_m2 = tokio::time::timeout(Duration::from_millis(50), rx2.recv()).await;
+        assert!(nothing_for_m2.is_err());
+    }
+
+    #[tokio::test]
+    async fn shared_subscription_delivery_round_robins_across_members() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        router.commitlog.insert("a/b".to_owned(), vec![publish("a/b".to_owned(), QoS::AtMostOnce, "1".to_owned())]);
+
+        let (tx1, mut rx1) = tokio::sync::mpsc::channel(8);
+        let (tx2, mut rx2) = tokio::sync::mpsc::channel(8);
+        router.active_connections.insert("m1".to_owned(), ActiveConnection::new_internal(tx1));
+        router.active_connections.insert("m2".to_owned(), ActiveConnection::new_internal(tx2));
+
+        let mut group = SharedGroup::new(QoS::AtMostOnce);
+        group.add_member("m1".to_owned());
+        group.add_member("m2".to_owned());
+        router.shared_subscriptions.insert(("g".to_owned(), "a/b".to_owned()), group);
+
+        // First publish lands on m1 (the cursor starts at member 0), which rotates the cursor
+        // to m2 for next time.
+        router.route();
+        let _ = rx1.recv().await.unwrap();
+
+        router.commitlog.get_mut("a/b").unwrap().push(publish("a/b".to_owned(), QoS::AtMostOnce, "2".to_owned()));
+        router.route();
+
+        match rx2.recv().await.unwrap() {
+            RouterMessage::Publishes(publishes) => assert_eq!(publishes.len(), 1),
+            other => panic!("expected exactly one batch of publishes, got {:?}", other),
+        }
+        let nothing_for_m1 = tokio::time::timeout(Duration::from_millis(50), rx1.recv()).await;
+        assert!(nothing_for_m1.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_full_group_member_is_skipped_in_favor_of_the_next_one_in_rotation() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        router.commitlog.insert("a/b".to_owned(), vec![publish("a/b".to_owned(), QoS::AtMostOnce, "1".to_owned())]);
+
+        // m1's channel has a capacity of exactly one slot, already filled - its next `try_send`
+        // inside `route` comes back `Full`, so delivery must fall through to m2.
+        let (mut tx1, _rx1) = tokio::sync::mpsc::channel(1);
+        tx1.try_send(RouterMessage::Publishes(Vec::new())).unwrap();
+        let (tx2, mut rx2) = tokio::sync::mpsc::channel(8);
+        router.active_connections.insert("m1".to_owned(), ActiveConnection::new_internal(tx1));
+        router.active_connections.insert("m2".to_owned(), ActiveConnection::new_internal(tx2));
+
+        let mut group = SharedGroup::new(QoS::AtMostOnce);
+        group.add_member("m1".to_owned());
+        group.add_member("m2".to_owned());
+        router.shared_subscriptions.insert(("g".to_owned(), "a/b".to_owned()), group);
+
+        router.route();
+
+        match rx2.recv().await.unwrap() {
+            RouterMessage::Publishes(publishes) => assert_eq!(publishes.len(), 1),
+            other => panic!("expected exactly one batch of publishes, got {:?}", other),
+        }
+
+        // `route` ends by compacting the log: the delivered entry is drained and the group's
+        // offset shifted back by one, from 1 to 0.
+        let group = &router.shared_subscriptions[&("g".to_owned(), "a/b".to_owned())];
+        assert_eq!(group.offset, 0);
+        assert!(router.commitlog["a/b"].is_empty());
+        // Cursor lands one past m2 (index 1), wrapping back to m1 for next time.
+        assert_eq!(group.cursor, 0);
+    }
+
+    #[test]
+    fn unsubscribing_removes_the_member_from_its_shared_group() {
+        let mut group = SharedGroup::new(QoS::AtMostOnce);
+        group.add_member("m1".to_owned());
+        group.add_member("m2".to_owned());
+        group.cursor = 1;
+
+        group.remove_member("m1");
+
+        assert_eq!(group.members, vec!["m2".to_owned()]);
+        // Removing a member can shrink the member list past where the cursor pointed - reset to
+        // 0 rather than leaving it out of bounds for the next round-robin attempt.
+        assert_eq!(group.cursor, 0);
+    }
+
+    #[test]
+    fn topic_trie_matches_plus_and_hash_wildcards_against_concrete_topics() {
+        let mut trie = TopicTrie::new();
+        trie.insert("a/+/c");
+        trie.insert("a/b/#");
+        trie.insert("x/y");
+
+        let mut matched = trie.matching_filters("a/b/c");
+        matched.sort();
+        assert_eq!(matched, vec!["a/+/c".to_owned(), "a/b/#".to_owned()]);
+
+        assert_eq!(trie.matching_filters("x/y"), vec!["x/y".to_owned()]);
+        assert!(trie.matching_filters("x/z").is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_publish_matching_two_overlapping_wildcard_subscriptions_is_delivered_once() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        router.commitlog.insert("a/b/c".to_owned(), vec![publish("a/b/c".to_owned(), QoS::AtMostOnce, "hi".to_owned())]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        // Two non-subsuming wildcard filters both matching "a/b/c" - `fix_overlapping_subscriptions`
+        // only merges a filter into another when one subsumes the other, so both survive here.
+        connection.wild_subscriptions.insert("a/+/c".to_owned(), Subscription::new(QoS::AtMostOnce));
+        connection.wild_subscriptions.insert("a/b/+".to_owned(), Subscription::new(QoS::AtMostOnce));
+        router.active_connections.insert("c1".to_owned(), connection);
+
+        router.route();
+
+        match rx.recv().await.unwrap() {
+            RouterMessage::Publishes(publishes) => assert_eq!(publishes.len(), 1),
+            other => panic!("expected exactly one batch of publishes, got {:?}", other),
+        }
+
+        // Both overlapping filters must have been advanced past the publish, or the next tick
+        // would re-deliver it through whichever filter was left behind. With both at 1, `route`'s
+        // compaction drains the entry and shifts both cursors back to 0.
+        let connection = &router.active_connections["c1"];
+        assert_eq!(connection.wild_subscriptions["a/+/c"].offset("a/b/c"), 0);
+        assert_eq!(connection.wild_subscriptions["a/b/+"].offset("a/b/c"), 0);
+        assert!(router.commitlog["a/b/c"].is_empty());
+
+        router.route();
+        let redelivered = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
+        assert!(redelivered.is_err(), "publish should not be redelivered once both filters are caught up");
+    }
+
+    #[tokio::test]
+    async fn credit_clipping_does_not_underflow_a_wildcard_filter_already_ahead_of_the_clipped_batch() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        router.commitlog.insert(
+            "a/b/c".to_owned(),
+            vec![
+                publish("a/b/c".to_owned(), QoS::AtLeastOnce, "one".to_owned()),
+                publish("a/b/c".to_owned(), QoS::AtLeastOnce, "two".to_owned()),
+                publish("a/b/c".to_owned(), QoS::AtLeastOnce, "three".to_owned()),
+            ],
+        );
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let mut connection = ActiveConnection::new_internal(tx);
+        connection.wild_subscriptions.insert("a/+/c".to_owned(), Subscription::new(QoS::AtLeastOnce));
+        // "a/b/+" is already caught up to offset 2, well ahead of where the credit-clipped batch
+        // (computed from the global minimum offset of 0) will stop.
+        let mut ahead = Subscription::new(QoS::AtLeastOnce);
+        ahead.advance("a/b/c", 2);
+        connection.wild_subscriptions.insert("a/b/+".to_owned(), ahead);
+        // Only enough credit for one publish, so `caught_up_to` (1) ends up behind "a/b/+"'s
+        // offset (2) - this used to underflow the `usize` subtraction.
+        connection.send_credit = 1;
+        router.active_connections.insert("c1".to_owned(), connection);
+
+        router.route();
+
+        match rx.recv().await.unwrap() {
+            RouterMessage::Publishes(publishes) => assert_eq!(publishes.len(), 1),
+            other => panic!("expected exactly one publish, got {:?}", other),
+        }
+
+        // "a/+/c" reaches 1 and "a/b/+" stays at 2; `route`'s compaction then drains the one
+        // entry both have read and shifts both cursors back by one.
+        let connection = &router.active_connections["c1"];
+        assert_eq!(connection.wild_subscriptions["a/+/c"].offset("a/b/c"), 0);
+        // The already-ahead filter must be left untouched rather than moved backwards.
+        assert_eq!(connection.wild_subscriptions["a/b/+"].offset("a/b/c"), 1);
+    }
+
+    #[test]
+    fn a_staged_will_publishes_once_its_will_delay_elapses() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        let will = publish("client/lwt".to_owned(), QoS::AtMostOnce, "offline".to_owned());
+        router.pending_wills.insert("c1".to_owned(), (Instant::now() - Duration::from_secs(1), will));
+
+        router.sweep_due_wills();
+
+        assert!(!router.pending_wills.contains_key("c1"));
+        assert_eq!(router.commitlog["client/lwt"].len(), 1);
+    }
+
+    #[test]
+    fn a_reconnect_before_the_will_delay_elapses_cancels_the_staged_will() {
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        let will = publish("client/lwt".to_owned(), QoS::AtMostOnce, "offline".to_owned());
+        router.pending_wills.insert("c1".to_owned(), (Instant::now() + Duration::from_secs(30), will));
+
+        // Mirrors the first thing `handle_connect` does for a reconnecting client id (see its
+        // doc comment) - `rumq_core::mqtt4::Connect` lives outside this tree and can't be
+        // constructed here, so the cancellation is exercised directly instead of through
+        // `handle_connect` itself.
+        router.pending_wills.remove("c1");
+
+        assert!(!router.pending_wills.contains_key("c1"));
+
+        // Sweeping before the deadline would have been a bug even without the cancellation -
+        // confirm it doesn't publish a will that's already gone.
+        router.sweep_due_wills();
+        assert!(!router.commitlog.contains_key("client/lwt"));
+    }
+
+    #[test]
+    fn deactivating_a_connection_removes_it_from_every_shared_group() {
+        // The state-error arm of `handle_incoming_packet` reaches `deactivate_and_forward_will`
+        // to stage the will, same as a keep-alive timeout or a plain disconnect; driving that
+        // arm end-to-end needs a constructible `crate::state::MqttState`, which doesn't exist in
+        // this tree (there's no `state` module here). What's independently real and testable is
+        // that deactivation - regardless of what triggered it - always pulls the client out of
+        // every shared group it belonged to, so a later delivery never targets it.
+        let (_data_tx, data_rx) = tokio::sync::mpsc::channel(8);
+        let mut router = Router::new(data_rx, 3, Duration::from_secs(1));
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        router.active_connections.insert("c1".to_owned(), ActiveConnection::new_internal(tx));
+
+        let mut group = SharedGroup::new(QoS::AtMostOnce);
+        group.add_member("c1".to_owned());
+        router.shared_subscriptions.insert(("g".to_owned(), "a/b".to_owned()), group);
+
+        router.deactivate_and_forward_will("c1".to_owned());
+
+        assert!(!router.active_connections.contains_key("c1"));
+        let group = &router.shared_subscriptions[&("g".to_owned(), "a/b".to_owned())];
+        assert!(group.members.is_empty());
+    }
+}
@@ -1,12 +1,91 @@
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
 
 use derive_more::From;
-use rumq_core::mqtt4::{Packet, Publish};
+use rumq_core::mqtt4::{Publish, QoS};
 
 #[derive(Debug, From)]
 pub enum Error {
     Time(SystemTimeError),
+    Io(io::Error),
+    Eof,
+    InvalidQoS(u8),
+}
+
+// Every this many bytes written to a segment's `.log` file, we push a sparse index entry
+// mapping the record offset to its byte position. Trades a small linear scan at `get` time
+// for an index that stays tiny even for very large segments.
+const SPARSE_INDEX_INTERVAL: u64 = 4 * 1024;
+
+/// Serializes a publish as `dup | retain` flags (u8), qos (u8), pkid (u16), topic (u16 len +
+/// utf8 bytes), payload (u32 len + raw bytes). This is an on-disk framing local to the
+/// commitlog, not the MQTT wire format - the commitlog only ever talks to itself.
+fn encode_publish(publish: &Publish) -> Vec<u8> {
+    let mut record = Vec::with_capacity(8 + publish.topic_name.len() + publish.payload.len());
+
+    let flags = (publish.dup as u8) | ((publish.retain as u8) << 1);
+    record.push(flags);
+    record.push(publish.qos as u8);
+    record.extend_from_slice(&publish.pkid.to_be_bytes());
+
+    record.extend_from_slice(&(publish.topic_name.len() as u16).to_be_bytes());
+    record.extend_from_slice(publish.topic_name.as_bytes());
+
+    record.extend_from_slice(&(publish.payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&publish.payload);
+
+    record
+}
+
+fn decode_publish(mut record: &[u8]) -> Result<Publish, Error> {
+    if record.len() < 4 {
+        return Err(Error::Eof);
+    }
+
+    let flags = record[0];
+    let qos = match record[1] {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        q => return Err(Error::InvalidQoS(q)),
+    };
+    let pkid = u16::from_be_bytes([record[2], record[3]]);
+    record = &record[4..];
+
+    if record.len() < 2 {
+        return Err(Error::Eof);
+    }
+    let topic_len = u16::from_be_bytes([record[0], record[1]]) as usize;
+    record = &record[2..];
+    if record.len() < topic_len {
+        return Err(Error::Eof);
+    }
+    let topic_name = String::from_utf8_lossy(&record[..topic_len]).into_owned();
+    record = &record[topic_len..];
+
+    if record.len() < 4 {
+        return Err(Error::Eof);
+    }
+    let payload_len = u32::from_be_bytes([record[0], record[1], record[2], record[3]]) as usize;
+    record = &record[4..];
+    if record.len() < payload_len {
+        return Err(Error::Eof);
+    }
+    let payload = record[..payload_len].to_vec();
+
+    Ok(Publish {
+        dup: flags & 0b01 != 0,
+        retain: flags & 0b10 != 0,
+        qos,
+        pkid,
+        topic_name,
+        payload,
+    })
 }
 
 #[derive(Debug)]
@@ -23,82 +102,346 @@ pub struct Messages {
 struct Segment {
     // id of this segment
     pub id: u64,
-    // current size of this segment
+    // current size of this segment (sum of payload bytes, used for rollover)
     pub current_size: usize,
     // max_allowed size of the segment
     max_size: usize,
     // timestamp when the log is created
     timestamp: u128,
-    // all the packets in this segment
-    packets: Vec<Publish>,
+    log_path: PathBuf,
+    index_path: PathBuf,
+    log_file: File,
+    // sparse index: record offset (relative to this segment) -> byte position in `log_file`
+    index: Vec<(u32, u64)>,
+    // number of records written to this segment so far
+    count: u32,
+    // bytes written to `log_file` since the last index entry was pushed
+    bytes_since_index: u64,
 }
 
 impl Segment {
-    pub fn new(id: u64, max_size: usize) -> Result<Segment, Error> {
+    pub fn new(dir: &Path, id: u64, max_size: usize) -> Result<Segment, Error> {
+        fs::create_dir_all(dir)?;
+
+        let log_path = dir.join(format!("{:020}.log", id));
+        let index_path = dir.join(format!("{:020}.index", id));
+        let log_file = OpenOptions::new().create(true).read(true).append(true).open(&log_path)?;
+        // truncate a stale index left over from a previous segment with the same id, if any
+        let _ = fs::remove_file(&index_path);
+
         let segment = Segment {
             id,
             current_size: 0,
             max_size,
             timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
-            packets: Vec::new(),
+            log_path,
+            index_path,
+            log_file,
+            index: Vec::new(),
+            count: 0,
+            bytes_since_index: 0,
         };
 
         Ok(segment)
     }
 
+    /// Rebuilds a segment from an existing `.log` file on disk, truncating a torn trailing
+    /// record left behind by a crash mid-write.
+    fn recover(dir: &Path, id: u64, max_size: usize) -> Result<Segment, Error> {
+        let log_path = dir.join(format!("{:020}.log", id));
+        let index_path = dir.join(format!("{:020}.index", id));
+        let mut log_file = OpenOptions::new().read(true).write(true).append(true).open(&log_path)?;
+        let file_len = log_file.metadata()?.len();
+
+        let mut index = Vec::new();
+        let mut count: u32 = 0;
+        let mut current_size: usize = 0;
+        let mut bytes_since_index: u64 = 0;
+        let mut position: u64 = 0;
+
+        loop {
+            if position + 4 > file_len {
+                break;
+            }
+
+            log_file.seek(SeekFrom::Start(position))?;
+            let mut len_buf = [0u8; 4];
+            log_file.read_exact(&mut len_buf)?;
+            let record_len = u32::from_be_bytes(len_buf) as u64;
+
+            // the length prefix claims more bytes than the file actually has - the process
+            // must have crashed mid-write. Drop the torn record and stop recovering.
+            if position + 4 + record_len > file_len {
+                log_file.set_len(position)?;
+                break;
+            }
+
+            if index.is_empty() || bytes_since_index >= SPARSE_INDEX_INTERVAL {
+                index.push((count, position));
+                bytes_since_index = 0;
+            }
+
+            let mut record = vec![0u8; record_len as usize];
+            log_file.read_exact(&mut record)?;
+            let publish = decode_publish(&record)?;
+            current_size += publish.payload.len();
+
+            let framed_len = 4 + record_len;
+            position += framed_len;
+            bytes_since_index += framed_len;
+            count += 1;
+        }
+
+        // the on-disk index might be stale (or missing) after a crash - rewrite it to match
+        // what recovery actually found.
+        let mut index_file = OpenOptions::new().create(true).write(true).truncate(true).open(&index_path)?;
+        for (offset, byte_position) in &index {
+            index_file.write_all(&offset.to_be_bytes())?;
+            index_file.write_all(&byte_position.to_be_bytes())?;
+        }
+
+        log_file.seek(SeekFrom::End(0))?;
+
+        Ok(Segment {
+            id,
+            current_size,
+            max_size,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            log_path,
+            index_path,
+            log_file,
+            index,
+            count,
+            bytes_since_index,
+        })
+    }
+
     // Fills the segment with given publish. If the segment is full, returns
     // Some(offset of the last element) in the segment
-    pub fn fill(&mut self, pubilsh: Publish) -> Option<u64> {
+    pub fn fill(&mut self, pubilsh: Publish) -> Result<Option<u64>, Error> {
         let payload_size = pubilsh.payload.len();
-        self.packets.push(pubilsh);
+        let record = encode_publish(&pubilsh);
+
+        if self.index.is_empty() || self.bytes_since_index >= SPARSE_INDEX_INTERVAL {
+            let byte_position = self.log_file.seek(SeekFrom::End(0))?;
+            self.index.push((self.count, byte_position));
+
+            let mut index_file = OpenOptions::new().create(true).append(true).open(&self.index_path)?;
+            index_file.write_all(&self.count.to_be_bytes())?;
+            index_file.write_all(&byte_position.to_be_bytes())?;
+
+            self.bytes_since_index = 0;
+        }
+
+        self.log_file.write_all(&(record.len() as u32).to_be_bytes())?;
+        self.log_file.write_all(&record)?;
+        self.log_file.flush()?;
+
+        self.bytes_since_index += 4 + record.len() as u64;
+        self.count += 1;
         self.current_size += payload_size;
 
         if self.current_size >= self.max_size {
-            return Some(self.packets.len() as u64 - 1);
+            return Ok(Some(self.count as u64 - 1));
         }
 
-        None
+        Ok(None)
+    }
+
+    /// Binary searches the sparse index for the entry at or before `from`, seeks there and
+    /// decodes forward, skipping records before `from`, until `count` records are collected
+    /// or the segment runs out.
+    fn read_from(&mut self, from: u32, count: usize) -> Result<Vec<Publish>, Error> {
+        if from as u64 >= self.count as u64 {
+            return Ok(Vec::new());
+        }
+
+        let nearest = match self.index.binary_search_by_key(&from, |(offset, _)| *offset) {
+            Ok(i) => i,
+            Err(0) => 0,
+            Err(i) => i - 1,
+        };
+        let (mut record_offset, byte_position) = self.index[nearest];
+        self.log_file.seek(SeekFrom::Start(byte_position))?;
+
+        let mut publishes = Vec::new();
+        while (record_offset as u64) < self.count as u64 && publishes.len() < count {
+            let mut len_buf = [0u8; 4];
+            self.log_file.read_exact(&mut len_buf)?;
+            let record_len = u32::from_be_bytes(len_buf) as usize;
+
+            let mut record = vec![0u8; record_len];
+            self.log_file.read_exact(&mut record)?;
+
+            if record_offset >= from {
+                publishes.push(decode_publish(&record)?);
+            }
+
+            record_offset += 1;
+        }
+
+        Ok(publishes)
     }
 }
 
+/// Retention limits enforced by [`CommitLog::enforce_retention`], on top of the fixed
+/// `segments_per_partition` ring that `fill` already maintains. `None` means that dimension is
+/// unbounded. At least one segment per partition - the active one - is always kept regardless
+/// of policy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    pub max_bytes_per_partition: Option<usize>,
+    pub max_age_millis: Option<u128>,
+}
+
 #[derive(Debug)]
 pub struct CommitLog {
+    dir: PathBuf,
     partitions: HashMap<String, Vec<Segment>>,
     max_segement_size: usize,
     segments_per_partition: usize,
     current_segment_id: u64,
+    retention: RetentionPolicy,
 }
 
 impl CommitLog {
-    pub fn new(max_segement_size: usize, segments_per_partition: usize) -> Result<CommitLog, Error> {
-        let commitlog =
-            CommitLog { partitions: HashMap::new(), max_segement_size, segments_per_partition, current_segment_id: 0 };
+    /// Opens (or creates) a commitlog rooted at `dir`, recovering any partitions already
+    /// persisted there from a previous run.
+    pub fn new<P: Into<PathBuf>>(
+        dir: P,
+        max_segement_size: usize,
+        segments_per_partition: usize,
+        retention: RetentionPolicy,
+    ) -> Result<CommitLog, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut partitions = HashMap::new();
+        let mut current_segment_id = 0;
+
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let partition_dir = entry.path();
+            let topic = match fs::read_to_string(partition_dir.join("topic.txt")) {
+                Ok(topic) => topic,
+                Err(_) => continue,
+            };
+
+            let segments = Self::recover_partition(&partition_dir, max_segement_size)?;
+            current_segment_id = segments.iter().fold(current_segment_id, |max, s| max.max(s.id));
+
+            if !segments.is_empty() {
+                partitions.insert(topic, segments);
+            }
+        }
 
+        let commitlog = CommitLog { dir, partitions, max_segement_size, segments_per_partition, current_segment_id, retention };
         Ok(commitlog)
     }
 
+    /// Drops the oldest segments of every partition that fall outside the retention policy -
+    /// too old, or pushing the partition over its byte budget - deleting their files from disk.
+    /// Meant to be called periodically (e.g. alongside the router's routing tick).
+    pub fn enforce_retention(&mut self) {
+        let retention = self.retention;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+
+        for segments in self.partitions.values_mut() {
+            loop {
+                if segments.len() <= 1 {
+                    break;
+                }
+
+                let too_old = retention
+                    .max_age_millis
+                    .map_or(false, |max_age| now.saturating_sub(segments[0].timestamp) > max_age);
+                let too_big = retention.max_bytes_per_partition.map_or(false, |max_bytes| {
+                    segments.iter().map(|s| s.current_size).sum::<usize>() > max_bytes
+                });
+
+                if !too_old && !too_big {
+                    break;
+                }
+
+                let oldest = segments.remove(0);
+                let _ = fs::remove_file(&oldest.log_path);
+                let _ = fs::remove_file(&oldest.index_path);
+            }
+        }
+    }
+
+    /// The earliest `(segment_id, log_offset)` still retained for `topic`. A consumer whose
+    /// tracked offset falls behind this (because `fill` or `enforce_retention` evicted the
+    /// segment it was reading from) has fallen off the log and should reset to this position
+    /// instead of treating a `None` from `get` as "caught up".
+    pub fn earliest(&self, topic: &str) -> Option<(u64, usize)> {
+        self.partitions.get(topic).and_then(|segments| segments.first()).map(|segment| (segment.id, 0))
+    }
+
+    /// Every partition (topic) currently known to this commitlog, recovered or filled. Used by
+    /// `Router::set_commitlog_dir` to replay each partition into the in-memory commitlog once at
+    /// startup.
+    pub fn topics(&self) -> Vec<String> {
+        self.partitions.keys().cloned().collect()
+    }
+
+    fn recover_partition(partition_dir: &Path, max_size: usize) -> Result<Vec<Segment>, Error> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(partition_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("log") {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u64>().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        ids.into_iter().map(|id| Segment::recover(partition_dir, id, max_size)).collect()
+    }
+
+    // directory names must just be filesystem-safe and collision resistant; the real topic
+    // string is kept alongside in `topic.txt` so recovery doesn't need to reverse this.
+    fn partition_dir(&self, topic: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        topic.hash(&mut hasher);
+        self.dir.join(format!("{:016x}", hasher.finish()))
+    }
+
     pub fn fill(&mut self, publish: Publish) -> Result<(), Error> {
         if let Some(segments) = self.partitions.get_mut(&publish.topic_name) {
             // fill the last segment of the partition. Always exists and this is the only function
             // which can delete segment
+            let dir = self.partition_dir(&publish.topic_name);
             let segment = segments.last_mut().unwrap();
-            if let Some(_) = segment.fill(publish) {
-                // delete the first segment when the number of segments are at limit
+            if let Some(_) = segment.fill(publish)? {
+                // delete the first segment (and its files) when the number of segments are at limit
                 if segments.len() >= self.segments_per_partition {
-                    segments.remove(0);
+                    let oldest = segments.remove(0);
+                    let _ = fs::remove_file(&oldest.log_path);
+                    let _ = fs::remove_file(&oldest.index_path);
                 }
 
                 // push a new segment
                 self.current_segment_id += 1;
-                let segment = Segment::new(self.current_segment_id, self.max_segement_size)?;
+                let segment = Segment::new(&dir, self.current_segment_id, self.max_segement_size)?;
                 segments.push(segment);
             }
         } else {
             // create a new partition with this new topic
             let topic = publish.topic_name.clone();
+            let dir = self.partition_dir(&topic);
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join("topic.txt"), &topic)?;
+
             let mut partition = Vec::new();
-            let mut segment = Segment::new(0, self.max_segement_size)?;
-            segment.fill(publish);
+            let mut segment = Segment::new(&dir, 0, self.max_segement_size)?;
+            segment.fill(publish)?;
             partition.push(segment);
             self.partitions.insert(topic, partition);
         }
@@ -108,8 +451,8 @@ impl CommitLog {
 
     // get a maximum of n elements from partition's segments
     // return's the segment id and log offset of the last element of the batch
-    pub fn get(&self, topic: &str, segment_id: u64, mut log_offset: usize, mut count: usize) -> Option<Messages> {
-        let segments = match self.partitions.get(topic) {
+    pub fn get(&mut self, topic: &str, segment_id: u64, mut log_offset: usize, mut count: usize) -> Option<Messages> {
+        let segments = match self.partitions.get_mut(topic) {
             Some(segments) => segments,
             None => return None,
         };
@@ -131,17 +474,25 @@ impl CommitLog {
 
         // fill publishes
         let mut messages = Messages { segment_id: 0, log_offset: 0, packets: Vec::new() };
-        for segment in segments.split_at(segment_index).1.iter() {
+        for segment in segments.split_at_mut(segment_index).1.iter_mut() {
             // continue to next segment if the given log offset doesn't exist
-            if segment.packets.get(log_offset).is_none() {
+            if log_offset as u64 >= segment.count as u64 {
                 log_offset = 0;
                 continue;
             }
 
-            let o: Vec<Publish> = segment.packets.split_at(log_offset).1.iter().take(count).cloned().collect();
+            let o = match segment.read_from(log_offset as u32, count) {
+                Ok(o) => o,
+                Err(_) => break,
+            };
             let collected_message_count = o.len();
+            if collected_message_count == 0 {
+                log_offset = 0;
+                continue;
+            }
+
             messages.segment_id = segment.id;
-            messages.log_offset = log_offset + o.len() - 1;
+            messages.log_offset = log_offset + collected_message_count - 1;
             messages.packets.extend(o);
 
             if collected_message_count >= count {
@@ -150,6 +501,7 @@ impl CommitLog {
 
             // decrease the collection count for the next iteration
             count = count - collected_message_count;
+            log_offset = 0;
         }
 
         if messages.packets.len() > 0 {
@@ -162,20 +514,21 @@ impl CommitLog {
 
 #[cfg(test)]
 mod test {
-    use super::{CommitLog, Segment};
+    use super::{CommitLog, RetentionPolicy, Segment};
     use rumq_core::mqtt4::{publish, QoS};
 
     #[test]
     fn filled_segment_returns_correct_offset() {
-        let mut segment = Segment::new(0, 1024 * 3).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut segment = Segment::new(dir.path(), 0, 1024 * 3).unwrap();
         let payload = vec![0; 1024];
 
         let publish = publish("hello/world", QoS::AtLeastOnce, payload);
 
-        assert!(segment.fill(publish.clone()).is_none());
-        assert!(segment.fill(publish.clone()).is_none());
+        assert!(segment.fill(publish.clone()).unwrap().is_none());
+        assert!(segment.fill(publish.clone()).unwrap().is_none());
 
-        match segment.fill(publish.clone()) {
+        match segment.fill(publish.clone()).unwrap() {
             Some(offset) => assert_eq!(2, offset),
             None => panic!("Segment should've been full by now"),
         }
@@ -183,8 +536,9 @@ mod test {
 
     #[test]
     fn commit_log_fills_correctly() {
+        let dir = tempfile::tempdir().unwrap();
         // 10 segments. Each segment size = 10K
-        let mut commitlog = CommitLog::new(10 * 1024, 10).unwrap();
+        let mut commitlog = CommitLog::new(dir.path(), 10 * 1024, 10, RetentionPolicy::default()).unwrap();
 
         let payload = vec![0; 1024];
         let publish = publish("hello/world", QoS::AtLeastOnce, payload);
@@ -200,8 +554,9 @@ mod test {
 
     #[test]
     fn commitlog_returns_data_and_offset_correctly() {
+        let dir = tempfile::tempdir().unwrap();
         // max 10 segments. Each segment size = 10K. Max 100KB in total
-        let mut commitlog = CommitLog::new(10 * 1024, 10).unwrap();
+        let mut commitlog = CommitLog::new(dir.path(), 10 * 1024, 10, RetentionPolicy::default()).unwrap();
 
         let o = commitlog.get("hello/world", 0, 0, 10);
         assert!(o.is_none());
@@ -238,4 +593,69 @@ mod test {
         assert_eq!(o.segment_id, 9);
         assert_eq!(o.log_offset, 9);
     }
+
+    #[test]
+    fn commitlog_recovers_from_disk_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let payload = vec![0; 1024];
+        let publish = publish("hello/world", QoS::AtLeastOnce, payload);
+
+        {
+            let mut commitlog = CommitLog::new(dir.path(), 10 * 1024, 10, RetentionPolicy::default()).unwrap();
+            for _ in 0..25 {
+                commitlog.fill(publish.clone()).unwrap()
+            }
+        }
+
+        // reopening the same directory should rebuild the partitions from the `.log` files
+        let mut commitlog = CommitLog::new(dir.path(), 10 * 1024, 10, RetentionPolicy::default()).unwrap();
+        // `get` reads on across segments 0, 1 and 2 (10 + 10 + 5 records)
+        let o = commitlog.get("hello/world", 0, 0, 100).unwrap();
+        assert_eq!(o.packets.len(), 25);
+    }
+
+    #[test]
+    fn enforce_retention_drops_oldest_segments_over_the_byte_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        // max 10 segments of 10K each, but retention caps the partition at 25K total
+        let retention = RetentionPolicy { max_bytes_per_partition: Some(25 * 1024), max_age_millis: None };
+        let mut commitlog = CommitLog::new(dir.path(), 10 * 1024, 10, retention).unwrap();
+
+        let payload = vec![0; 1024];
+        let publish = publish("hello/world", QoS::AtLeastOnce, payload);
+        for _ in 0..100 {
+            commitlog.fill(publish.clone()).unwrap()
+        }
+
+        commitlog.enforce_retention();
+
+        let partition = commitlog.partitions.get("hello/world").unwrap();
+        let total_size: usize = partition.iter().map(|s| s.current_size).sum();
+        assert!(total_size <= 25 * 1024, "total size {} should be under the retention budget", total_size);
+
+        // the active (not-yet-full) segment is always kept even if it alone would bust the budget
+        assert!(partition.len() >= 1);
+    }
+
+    #[test]
+    fn earliest_reports_the_first_retained_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = RetentionPolicy { max_bytes_per_partition: Some(20 * 1024), max_age_millis: None };
+        let mut commitlog = CommitLog::new(dir.path(), 10 * 1024, 10, retention).unwrap();
+
+        assert!(commitlog.earliest("hello/world").is_none());
+
+        let payload = vec![0; 1024];
+        let publish = publish("hello/world", QoS::AtLeastOnce, payload);
+        for _ in 0..100 {
+            commitlog.fill(publish.clone()).unwrap()
+        }
+        commitlog.enforce_retention();
+
+        let (segment_id, log_offset) = commitlog.earliest("hello/world").unwrap();
+        assert_eq!(log_offset, 0);
+
+        // a consumer still tracking an evicted segment is now behind the earliest retained one
+        assert!(segment_id > 0);
+    }
 }